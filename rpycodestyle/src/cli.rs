@@ -0,0 +1,203 @@
+use options::Options;
+
+pub const USAGE: &str = "\
+Usage: rpycodestyle [options] input ...
+       rpycodestyle explain code
+
+Options:
+  --version            show program's version number and exit
+  -h, --help           show this help message and exit
+  -q, --quiet          report only file names, or nothing with -qq
+  --first              show first occurrence of each error
+  --exclude=patterns   exclude files or directories which match these comma
+                       separated patterns (default: .svn,CVS,.bzr,.hg,.git,
+                       __pycache__,.tox,.nox,.eggs,*.egg)
+  --filename=patterns  when parsing directories, only check filenames matching
+                       these comma separated patterns (default: *.py)
+  --select=errors      select errors and warnings (e.g. E,W6)
+  --ignore=errors      skip errors and warnings (e.g. E4,W)
+  --extend-ignore=errors
+                       skip these errors and warnings in addition to --ignore
+  --per-file-ignores=entries
+                       skip errors and warnings in the files matching a
+                       pattern (e.g. tests/*.py:E501,E731 __init__.py:E402)
+  --show-source        show source code for each error
+  --show-pep8          show text of PEP 8 for each error (implies --first)
+  --statistics         count errors and warnings
+  --count              print total number of errors and warnings to standard
+                       error and set exit code to 1 if total is not null
+  --max-line-length=n  set maximum allowed line length (default: 79)
+  --max-doc-length=n   set maximum allowed doc line length and perform these
+                       checks (unchecked if not set)
+  --hang-closing       hang closing bracket instead of matching indentation of
+                       opening bracket's line
+  --format=format      set the error format [default|pylint|github|
+                       json|sarif|junit|checkstyle], or a template such as
+                       '%(path)s:%(row)d:%(col)d: %(code)s %(text)s'
+  --output-file=path   write the json, sarif, junit or checkstyle report to
+                       this file and the default report to standard output
+  --benchmark          measure processing speed
+  --config=path        user config file location
+";
+
+// Options which do not take a value.
+const FLAGS: &[&str] = &["first", "show-source", "show-pep8", "statistics", "count",
+                         "hang-closing", "benchmark"];
+// Options which take a value, either as --name=value or --name value.
+const VALUE_OPTIONS: &[&str] = &["exclude", "filename", "select", "ignore", "extend-ignore",
+                                 "max-line-length", "max-doc-length", "format", "config",
+                                 "output-file", "per-file-ignores"];
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Arguments {
+    pub paths: Vec<String>,
+    pub config: Option<String>,
+    // Options given on the command line as (name, value) pairs, in order.
+    pub options: Vec<(String, String)>,
+    pub help: bool,
+    pub version: bool,
+    // The code given to the explain subcommand.
+    pub explain: Option<String>,
+}
+
+impl Arguments {
+    // Override the values in options with the ones from the command line.
+    pub fn apply(&self, options: &mut Options) -> Result<(), String> {
+        for (name, value) in &self.options {
+            options.set(name, value)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_args(args: &[String]) -> Result<Arguments, String> {
+    let mut arguments = Arguments::default();
+    if args.first().is_some_and(|arg| arg == "explain") {
+        return match &args[1..] {
+            [code] => {
+                arguments.explain = Some(code.to_uppercase());
+                Ok(arguments)
+            }
+            _ => Err("explain takes exactly one code".to_string()),
+        }
+    }
+    let mut quiet = 0;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            arguments.paths.extend(args.by_ref().cloned());
+            break
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            arguments.paths.push(arg.clone());
+            continue
+        }
+        if !arg.starts_with("--") {
+            // Short options can be combined, as in -qq.
+            for short in arg.chars().skip(1) {
+                match short {
+                    'q' => quiet += 1,
+                    'h' => arguments.help = true,
+                    _ => return Err(format!("no such option: -{}", short)),
+                }
+            }
+            continue
+        }
+
+        let (name, value) = match arg[2..].find('=') {
+            Some(index) => (&arg[2..index + 2], Some(arg[index + 3..].to_string())),
+            None => (&arg[2..], None),
+        };
+        match name {
+            "help" => arguments.help = true,
+            "version" => arguments.version = true,
+            "quiet" => quiet += 1,
+            _ if FLAGS.contains(&name) => {
+                if value.is_some() {
+                    return Err(format!("--{} option does not take a value", name))
+                }
+                arguments.options.push((name.to_string(), "true".to_string()));
+            }
+            _ if VALUE_OPTIONS.contains(&name) => {
+                let value = match value.or_else(|| args.next().cloned()) {
+                    Some(value) => value,
+                    None => return Err(format!("--{} option requires 1 argument", name)),
+                };
+                if name == "config" {
+                    arguments.config = Some(value);
+                } else {
+                    arguments.options.push((name.to_string(), value));
+                }
+            }
+            _ => return Err(format!("no such option: --{}", name)),
+        }
+    }
+    if quiet > 0 {
+        arguments.options.push(("quiet".to_string(), quiet.to_string()));
+    }
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod test_cli {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_paths_and_options() {
+        let arguments = parse_args(&args(&["--select=E,W6", "a.py", "--max-line-length",
+                                           "100", "--count", "b.py"])).unwrap();
+        assert_eq!(arguments.paths, vec!["a.py", "b.py"]);
+        assert_eq!(arguments.options, vec![
+            ("select".to_string(), "E,W6".to_string()),
+            ("max-line-length".to_string(), "100".to_string()),
+            ("count".to_string(), "true".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn parse_args_quiet_and_config() {
+        let arguments = parse_args(&args(&["-qq", "--config", "tox.ini", "."])).unwrap();
+        assert_eq!(arguments.config, Some("tox.ini".to_string()));
+        assert_eq!(arguments.options, vec![("quiet".to_string(), "2".to_string())]);
+    }
+
+    #[test]
+    fn parse_args_after_double_dash() {
+        let arguments = parse_args(&args(&["--", "--first"])).unwrap();
+        assert_eq!(arguments.paths, vec!["--first"]);
+        assert!(arguments.options.is_empty());
+    }
+
+    #[test]
+    fn parse_args_errors() {
+        assert_eq!(parse_args(&args(&["--colour"])),
+                   Err("no such option: --colour".to_string()));
+        assert_eq!(parse_args(&args(&["--select"])),
+                   Err("--select option requires 1 argument".to_string()));
+        assert_eq!(parse_args(&args(&["--count=1"])),
+                   Err("--count option does not take a value".to_string()));
+    }
+
+    #[test]
+    fn parse_args_explain() {
+        let arguments = parse_args(&args(&["explain", "e231"])).unwrap();
+        assert_eq!(arguments.explain, Some("E231".to_string()));
+        assert_eq!(parse_args(&args(&["explain"])),
+                   Err("explain takes exactly one code".to_string()));
+        assert_eq!(parse_args(&args(&["a.py", "explain"])).unwrap().paths, vec!["a.py", "explain"]);
+    }
+
+    #[test]
+    fn arguments_apply() {
+        let arguments = parse_args(&args(&["--ignore=E501", "--hang-closing"])).unwrap();
+        let mut options = Options::default();
+        arguments.apply(&mut options).unwrap();
+        assert_eq!(options.ignore, vec!["E501"]);
+        assert!(options.hang_closing);
+    }
+}
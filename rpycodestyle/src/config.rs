@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use files::{absolute_path, normalize_path, FileFinder};
+use options::{parse_list, Options, OPTION_NAMES};
+
+pub const PROJECT_CONFIG: &[&str] = &["setup.cfg", "tox.ini"];
+pub const SECTIONS: &[&str] = &["pycodestyle", "pep8"];
+
+// (section, key, value) entries of an INI file, in order.  Keys are lower
+// cased, indented lines continue the previous value and lines starting with
+// "#" or ";" are comments, as with Python's configparser.
+pub fn parse_ini(source: &str) -> Vec<(String, String, String)> {
+    let mut entries: Vec<(String, String, String)> = Vec::new();
+    let mut section = String::new();
+    let mut continued = false;
+
+    for line in source.lines() {
+        let stripped = line.trim();
+        if stripped.is_empty() || stripped.starts_with('#') || stripped.starts_with(';') {
+            continue
+        }
+        if continued && line.starts_with([' ', '\t']) {
+            if let Some(entry) = entries.last_mut() {
+                entry.2.push('\n');
+                entry.2.push_str(stripped);
+            }
+            continue
+        }
+        continued = false;
+        if stripped.starts_with('[') && stripped.ends_with(']') {
+            section = stripped[1..stripped.len() - 1].trim().to_string();
+            continue
+        }
+        if let Some(index) = stripped.find(['=', ':']) {
+            let key = stripped[..index].trim().to_lowercase();
+            let value = stripped[index + 1..].trim().to_string();
+            entries.push((section.clone(), key, value));
+            continued = true;
+        }
+    }
+    entries
+}
+
+pub fn user_config_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("USERPROFILE").map(|home| PathBuf::from(home).join(".pycodestyle"))
+    }
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) => PathBuf::from(config_home),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("pycodestyle"))
+}
+
+// The project configuration files in the nearest directory holding one,
+// starting from the deepest directory shared by all the paths.
+pub fn project_config_paths(paths: &[String]) -> Vec<PathBuf> {
+    let mut parent = match common_path(paths) {
+        Some(parent) => parent,
+        None => return Vec::new(),
+    };
+    loop {
+        let found: Vec<PathBuf> = PROJECT_CONFIG.iter()
+            .map(|name| parent.join(name))
+            .filter(|path| path.is_file())
+            .collect();
+        if !found.is_empty() {
+            return found
+        }
+        if !parent.pop() {
+            return Vec::new()
+        }
+    }
+}
+
+fn common_path(paths: &[String]) -> Option<PathBuf> {
+    let mut paths = paths.iter().map(|path| absolute_path(Path::new(path)));
+    let mut common = paths.next()?;
+    for path in paths {
+        while !path.starts_with(&common) {
+            if !common.pop() {
+                return None
+            }
+        }
+    }
+    Some(common)
+}
+
+pub fn read_config_file(options: &mut Options, path: &Path) -> Result<Vec<String>, String> {
+//    Apply the [pycodestyle] section, or the legacy [pep8] one, of the file.
+//
+//    Returns warnings about unknown options, which are ignored.
+    let source = fs::read_to_string(path)
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    let entries = parse_ini(&source);
+    let section = match SECTIONS.iter()
+        .find(|&&section| entries.iter().any(|entry| entry.0 == section)) {
+        Some(section) => section,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut warnings = Vec::new();
+    let directory = path.parent().unwrap_or(Path::new("."));
+    for (_, key, value) in entries.iter().filter(|entry| entry.0 == *section) {
+        let key = key.replace('_', "-");
+        if !OPTION_NAMES.contains(&key.as_str()) {
+            warnings.push(format!("  unknown option '{}' ignored", key));
+            continue
+        }
+        let value = if key == "exclude" {
+            normalize_paths(value, directory)
+        } else {
+            value.clone()
+        };
+        options.set(&key, &value).map_err(|error| format!("{}: {}", path.display(), error))?;
+        if key == "per-file-ignores" {
+            normalize_per_file_ignores(options, directory);
+        }
+    }
+    Ok(warnings)
+}
+
+fn normalize_per_file_ignores(options: &mut Options, directory: &Path) {
+    for entry in &mut options.per_file_ignores {
+        entry.0 = normalize_path(&entry.0, directory);
+    }
+}
+
+// Resolve the patterns holding a "/" against the directory of the
+// configuration file.
+fn normalize_paths(value: &str, directory: &Path) -> String {
+    parse_list(value).iter()
+        .map(|pattern| normalize_path(pattern, directory))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+
+pub fn read_config(options: &mut Options, paths: &[String],
+                   user_config: Option<&Path>) -> Result<Vec<String>, String> {
+//    Read the user configuration then the project one, which overrides it.
+//
+//    user_config replaces the default user configuration file, it is an error
+//    for it to be missing.
+    let mut warnings = Vec::new();
+    match user_config {
+        Some(path) => warnings.extend(read_config_file(options, path)?),
+        None => {
+            if let Some(path) = user_config_path().filter(|path| path.is_file()) {
+                warnings.extend(read_config_file(options, &path)?);
+            }
+        }
+    }
+    for path in project_config_paths(paths) {
+        warnings.extend(read_config_file(options, &path)?);
+    }
+    Ok(warnings)
+}
+
+// The nearest pyproject.toml with a [tool.rpycodestyle] table in the
+// directory of path or one of its parents.  A file which cannot be parsed
+// is returned too, so that reading it reports the error.
+pub fn find_pyproject(path: &Path) -> Option<PathBuf> {
+    let mut directory = absolute_path(path);
+    while directory.pop() {
+        let pyproject = directory.join("pyproject.toml");
+        if pyproject.is_file() && has_rpycodestyle_table(&pyproject) != Some(false) {
+            return Some(pyproject)
+        }
+    }
+    None
+}
+
+fn has_rpycodestyle_table(path: &Path) -> Option<bool> {
+    let document: toml::Table = fs::read_to_string(path).ok()?.parse().ok()?;
+    Some(document.get("tool").and_then(|tool| tool.get("rpycodestyle")).is_some())
+}
+
+pub fn read_pyproject(options: &mut Options, path: &Path) -> Result<Vec<String>, String> {
+//    Apply the [tool.rpycodestyle] table of a pyproject.toml file.
+//
+//    Keys are the option names, values are strings, numbers, booleans or
+//    arrays of strings.  per-file-ignores is a table mapping file patterns to
+//    the codes to ignore in those files.  Patterns holding a "/" are relative
+//    to the directory of the file.
+    let error = |message: String| format!("{}: {}", path.display(), message);
+    let source = fs::read_to_string(path).map_err(|message| error(message.to_string()))?;
+    let document: toml::Table = source.parse().map_err(|message: toml::de::Error| {
+        error(message.message().to_string())
+    })?;
+    let table = match document.get("tool").and_then(|tool| tool.get("rpycodestyle")) {
+        Some(toml::Value::Table(table)) => table,
+        Some(_) => return Err(error("tool.rpycodestyle is not a table".to_string())),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut warnings = Vec::new();
+    let directory = path.parent().unwrap_or(Path::new("."));
+    for (key, value) in table {
+        let key = key.replace('_', "-");
+        if key == "per-file-ignores" {
+            let patterns = match *value {
+                toml::Value::Table(ref patterns) => patterns,
+                _ => return Err(error("per-file-ignores is not a table".to_string())),
+            };
+            let mut per_file_ignores = Vec::new();
+            for (pattern, codes) in patterns {
+                let codes = toml_option_value(codes).ok_or_else(|| {
+                    error(format!("invalid value for per-file-ignores: {}", codes))
+                })?;
+                per_file_ignores.push((normalize_path(pattern, directory), parse_list(&codes)));
+            }
+            options.per_file_ignores = per_file_ignores;
+            continue
+        }
+        if !OPTION_NAMES.contains(&key.as_str()) {
+            warnings.push(format!("  unknown option '{}' ignored", key));
+            continue
+        }
+        let value = toml_option_value(value)
+            .ok_or_else(|| error(format!("invalid value for {}: {}", key, value)))?;
+        let value = if key == "exclude" {
+            normalize_paths(&value, directory)
+        } else {
+            value
+        };
+        options.set(&key, &value).map_err(error)?;
+    }
+    Ok(warnings)
+}
+
+// The value as accepted by Options::set, arrays become comma separated lists.
+fn toml_option_value(value: &toml::Value) -> Option<String> {
+    match *value {
+        toml::Value::String(ref value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Array(ref values) => values.iter()
+            .map(|value| match *value {
+                toml::Value::String(ref value) => Some(value.clone()),
+                _ => None,
+            })
+            .collect::<Option<Vec<String>>>()
+            .map(|values| values.join(",")),
+        _ => None,
+    }
+}
+
+pub struct Configuration {
+    // Options from the defaults and the INI files, before the command line.
+    base: Options,
+    overrides: Vec<(String, String)>,
+    // The base options with the command line applied, used when no
+    // pyproject.toml applies.
+    pub options: Options,
+    projects: HashMap<PathBuf, Options>,
+    pub warnings: Vec<String>,
+}
+
+impl Configuration {
+    pub fn new(base: Options, overrides: Vec<(String, String)>) -> Result<Configuration, String> {
+        let mut options = base.clone();
+        for (name, value) in &overrides {
+            options.set(name, value)?;
+        }
+        Ok(Configuration {
+            base,
+            overrides,
+            options,
+            projects: HashMap::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    // The options for a file: the nearest pyproject.toml overrides the INI
+    // files and the command line overrides both.  Returns None when the
+    // file is excluded by the pyproject.toml.
+    pub fn for_file(&mut self, path: &Path) -> Result<Option<Options>, String> {
+        let pyproject = match find_pyproject(path) {
+            Some(pyproject) => pyproject,
+            None => return Ok(Some(self.options.for_path(path))),
+        };
+        if !self.projects.contains_key(&pyproject) {
+            let mut options = self.base.clone();
+            self.warnings.extend(read_pyproject(&mut options, &pyproject)?);
+            for (name, value) in &self.overrides {
+                options.set(name, value)?;
+            }
+            self.projects.insert(pyproject.clone(), options);
+        }
+        let options = &self.projects[&pyproject];
+        let root = pyproject.parent().unwrap_or(Path::new("/"));
+        if FileFinder::new(options).excluded_below(path, root) {
+            return Ok(None)
+        }
+        Ok(Some(options.for_path(path)))
+    }
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::*;
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("rpycodestyle-config-{}-{}", name,
+                                                std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        root
+    }
+
+    #[test]
+    fn parse_ini_sections_and_continuations() {
+        let entries = parse_ini("# comment\n[pycodestyle]\nMax-Line-Length = 100\n\
+                                 exclude = a,\n    b\n; other\n[flake8]\nselect: E\n");
+        assert_eq!(entries, vec![
+            ("pycodestyle".to_string(), "max-line-length".to_string(), "100".to_string()),
+            ("pycodestyle".to_string(), "exclude".to_string(), "a,\nb".to_string()),
+            ("flake8".to_string(), "select".to_string(), "E".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn read_config_file_sections() {
+        let root = temporary_directory("sections");
+        let path = root.join("setup.cfg");
+        fs::write(&path, "[pep8]\nmax-line-length = 90\n").unwrap();
+        let mut options = Options::default();
+        read_config_file(&mut options, &path).unwrap();
+        assert_eq!(options.max_line_length, 90);
+
+        fs::write(&path, "[pep8]\nmax-line-length = 90\n\
+                          [pycodestyle]\nmax_doc_length = 72\ncolour = 1\n").unwrap();
+        let mut options = Options::default();
+        let warnings = read_config_file(&mut options, &path).unwrap();
+        assert_eq!((options.max_line_length, options.max_doc_length), (79, Some(72)));
+        assert_eq!(warnings, vec!["  unknown option 'colour' ignored"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_config_file_invalid_value() {
+        let root = temporary_directory("invalid");
+        let path = root.join("tox.ini");
+        fs::write(&path, "[pycodestyle]\ncount = maybe\n").unwrap();
+        let error = read_config_file(&mut Options::default(), &path).unwrap_err();
+        assert!(error.ends_with("option --count: invalid boolean value: 'maybe'"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_config_project_files() {
+        let root = temporary_directory("project");
+        fs::write(root.join("setup.cfg"),
+                  "[pycodestyle]\nmax-line-length = 90\nignore = E1\nexclude = build/,x\n")
+            .unwrap();
+        fs::write(root.join("tox.ini"), "[pycodestyle]\nignore = E2\n").unwrap();
+        let user_config = root.join("user");
+        fs::write(&user_config, "[pycodestyle]\nmax-line-length = 80\ncount = yes\n").unwrap();
+
+        let mut options = Options::default();
+        let paths = vec![root.join("src").to_string_lossy().to_string()];
+        read_config(&mut options, &paths, Some(&user_config)).unwrap();
+        assert_eq!(options.max_line_length, 90);
+        assert_eq!(options.ignore, vec!["E2"]);
+        assert!(options.count);
+        assert_eq!(options.exclude, vec![root.join("build").to_string_lossy().to_string(),
+                                         "x".to_string()]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_pyproject_table() {
+        let root = temporary_directory("pyproject");
+        let path = root.join("pyproject.toml");
+        fs::write(&path, "[tool.black]\nline-length = 100\n\
+                          [tool.rpycodestyle]\nmax-line-length = 100\n\
+                          select = [\"E\", \"W6\"]\nignore = \"E501\"\n\
+                          exclude = [\"build/\", \"*.pyi\"]\ncolour = true\n\
+                          [tool.rpycodestyle.per-file-ignores]\n\"tests/*.py\" = [\"E501\"]\n")
+            .unwrap();
+        let mut options = Options::default();
+        let warnings = read_pyproject(&mut options, &path).unwrap();
+        assert_eq!(warnings, vec!["  unknown option 'colour' ignored"]);
+        assert_eq!(options.max_line_length, 100);
+        assert_eq!(options.select, vec!["E", "W6"]);
+        assert_eq!(options.ignore, vec!["E501"]);
+        assert_eq!(options.exclude, vec![root.join("build").to_string_lossy().to_string(),
+                                         "*.pyi".to_string()]);
+        assert_eq!(options.per_file_ignores,
+                   vec![(root.join("tests/*.py").to_string_lossy().to_string(),
+                         vec!["E501".to_string()])]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_pyproject_invalid_value() {
+        let root = temporary_directory("pyproject-invalid");
+        let path = root.join("pyproject.toml");
+        fs::write(&path, "[tool.rpycodestyle]\nmax-line-length = 1.5\n").unwrap();
+        let error = read_pyproject(&mut Options::default(), &path).unwrap_err();
+        assert!(error.ends_with("invalid value for max-line-length: 1.5"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn configuration_uses_nearest_pyproject() {
+        let root = temporary_directory("nearest");
+        fs::create_dir_all(root.join("sub/tests")).unwrap();
+        fs::write(root.join("pyproject.toml"),
+                  "[tool.rpycodestyle]\nmax-line-length = 100\n").unwrap();
+        fs::write(root.join("sub/pyproject.toml"),
+                  "[tool.rpycodestyle]\nmax-line-length = 120\nexclude = [\"gen\"]\n\
+                   [tool.rpycodestyle.per-file-ignores]\n\"tests/*.py\" = \"E501,W\"\n")
+            .unwrap();
+        let overrides = vec![("max-doc-length".to_string(), "72".to_string())];
+        let mut configuration = Configuration::new(Options::default(), overrides).unwrap();
+
+        let options = configuration.for_file(&root.join("src/a.py")).unwrap().unwrap();
+        assert_eq!((options.max_line_length, options.max_doc_length), (100, Some(72)));
+        let options = configuration.for_file(&root.join("sub/b.py")).unwrap().unwrap();
+        assert_eq!(options.max_line_length, 120);
+        assert!(options.file_ignore.is_empty());
+        let options = configuration.for_file(&root.join("sub/tests/c.py")).unwrap().unwrap();
+        assert_eq!(options.file_ignore, vec!["E501", "W"]);
+        assert_eq!(configuration.for_file(&root.join("sub/gen/d.py")).unwrap(), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn find_pyproject_skips_files_without_table() {
+        let root = temporary_directory("skip");
+        fs::create_dir_all(root.join("sub/package")).unwrap();
+        fs::write(root.join("pyproject.toml"),
+                  "[tool.rpycodestyle]\nmax-line-length = 100\n").unwrap();
+        fs::write(root.join("sub/pyproject.toml"), "[tool.black]\nline-length = 88\n").unwrap();
+        fs::write(root.join("sub/package/pyproject.toml"), "[tool\n").unwrap();
+        assert_eq!(find_pyproject(&root.join("sub/a.py")), Some(root.join("pyproject.toml")));
+        assert_eq!(find_pyproject(&root.join("sub/package/b.py")),
+                   Some(root.join("sub/package/pyproject.toml")));
+
+        let mut configuration = Configuration::new(Options::default(), Vec::new()).unwrap();
+        let options = configuration.for_file(&root.join("sub/a.py")).unwrap().unwrap();
+        assert_eq!(options.max_line_length, 100);
+        assert!(configuration.for_file(&root.join("sub/package/b.py")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn read_config_missing_user_config() {
+        let mut options = Options::default();
+        let result = read_config(&mut options, &[], Some(Path::new("does/not/exist")));
+        assert!(result.is_err());
+    }
+}
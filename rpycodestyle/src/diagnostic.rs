@@ -0,0 +1,113 @@
+use std::fmt;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Code(&'static str);
+
+impl Code {
+    pub const fn new(code: &'static str) -> Code {
+        Code(code)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    pub fn severity(&self) -> Severity {
+        if self.0.starts_with('W') {
+            Severity::Warning
+        } else {
+            Severity::Error
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    // Lines start at 1, columns are character offsets starting at 0 and
+    // end_column is exclusive.
+    pub line: usize,
+    pub column: usize,
+    pub end_column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(code: Code, line: usize, column: usize, message: String) -> Diagnostic {
+        Diagnostic {
+            code,
+            severity: code.severity(),
+            line,
+            column,
+            end_column: column + 1,
+            message,
+        }
+    }
+
+    pub fn with_end_column(mut self, end_column: usize) -> Diagnostic {
+        self.end_column = end_column.max(self.column);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.message)
+    }
+}
+
+#[cfg(test)]
+mod test_diagnostic {
+    use super::*;
+
+    #[test]
+    fn code_severity() {
+        assert_eq!(Code::new("E101").severity(), Severity::Error);
+        assert_eq!(Code::new("W291").severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn diagnostic_new() {
+        let diagnostic = Diagnostic::new(Code::new("W291"), 3, 11,
+                                         "trailing whitespace".to_string());
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!((diagnostic.line, diagnostic.column, diagnostic.end_column), (3, 11, 12));
+        assert_eq!(diagnostic.to_string(), "W291 trailing whitespace");
+    }
+
+    #[test]
+    fn diagnostic_with_end_column() {
+        let diagnostic = Diagnostic::new(Code::new("E501"), 1, 79,
+                                         "line too long (90 > 79 characters)".to_string())
+            .with_end_column(90);
+        assert_eq!(diagnostic.end_column, 90);
+    }
+}
@@ -0,0 +1,282 @@
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use options::Options;
+
+pub fn fnmatch(name: &str, pattern: &str) -> bool {
+//    Test whether name matches the shell-style pattern.
+//
+//    "*" matches everything, "?" matches any single character, "[seq]"
+//    matches any character in seq and "[!seq]" any character not in seq.
+//    Like Python's fnmatch, "*" also matches path separators.
+    let name: Vec<char> = name.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    fnmatch_chars(&name, &pattern)
+}
+
+fn fnmatch_chars(name: &[char], pattern: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| fnmatch_chars(&name[skip..], &pattern[1..])),
+        Some('?') => !name.is_empty() && fnmatch_chars(&name[1..], &pattern[1..]),
+        Some('[') => {
+            let (matched, length) = match match_class(name.first().cloned(), &pattern[1..]) {
+                Some(result) => result,
+                // An unterminated class matches a literal "[".
+                None => return name.first() == Some(&'[') &&
+                    fnmatch_chars(&name[1..], &pattern[1..]),
+            };
+            matched && fnmatch_chars(&name[1..], &pattern[length + 1..])
+        }
+        Some(&char) => name.first() == Some(&char) && fnmatch_chars(&name[1..], &pattern[1..]),
+    }
+}
+
+// Match a character against the class at the start of pattern, just after
+// its "[".  Returns whether it matched and the length of the class.
+fn match_class(char: Option<char>, pattern: &[char]) -> Option<(bool, usize)> {
+    let negate = pattern.first() == Some(&'!');
+    let mut index = if negate { 1 } else { 0 };
+    // A "]" right after the opening bracket is part of the class.
+    let start = index;
+    let mut matched = false;
+    while index < pattern.len() {
+        if pattern[index] == ']' && index > start {
+            let matched = char.is_some() && matched != negate;
+            return Some((matched, index + 1))
+        }
+        if index + 2 < pattern.len() && pattern[index + 1] == '-' && pattern[index + 2] != ']' {
+            if char.is_some_and(|char| pattern[index] <= char && char <= pattern[index + 2]) {
+                matched = true;
+            }
+            index += 3;
+        } else {
+            if char == Some(pattern[index]) {
+                matched = true;
+            }
+            index += 1;
+        }
+    }
+    None
+}
+
+pub fn filename_match(filename: &str, patterns: &[String], default: bool) -> bool {
+//    Check if patterns contains a pattern that matches filename.
+//
+//    If patterns is unspecified, this always returns the default.
+    if patterns.is_empty() {
+        return default
+    }
+    patterns.iter().any(|pattern| fnmatch(filename, pattern))
+}
+
+// Make a path absolute and remove "." and ".." components, like Python's
+// os.path.abspath.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+    normalized
+}
+
+// Resolve a pattern holding a "/" against directory, other patterns only
+// match basenames and are left as they are.
+pub fn normalize_path(pattern: &str, directory: &Path) -> String {
+    if pattern.contains('/') {
+        let path = absolute_path(&directory.join(pattern));
+        path.to_string_lossy().trim_end_matches('/').to_string()
+    } else {
+        pattern.to_string()
+    }
+}
+
+pub fn path_matches(path: &Path, patterns: &[String]) -> bool {
+//    Check if a pattern matches the basename or the absolute path.
+    if patterns.is_empty() {
+        return false
+    }
+    let basename = path.file_name().map_or(String::new(),
+                                           |name| name.to_string_lossy().to_string());
+    if filename_match(&basename, patterns, false) {
+        return true
+    }
+    let path = absolute_path(path);
+    filename_match(&path.to_string_lossy(), patterns, false)
+}
+
+pub struct FileFinder {
+    exclude: Vec<String>,
+    filename: Vec<String>,
+}
+
+impl FileFinder {
+    pub fn new(options: &Options) -> FileFinder {
+        // Exclude patterns holding a "/" are paths relative to the current
+        // directory, they are matched against absolute paths.
+        let exclude = options.exclude.iter()
+            .map(|pattern| normalize_path(pattern, Path::new(".")))
+            .collect();
+        FileFinder { exclude, filename: options.filename.clone() }
+    }
+
+    pub fn excluded(&self, path: &Path) -> bool {
+        path_matches(path, &self.exclude)
+    }
+
+    // Check if the path, or one of its parent directories below root, is
+    // excluded.
+    pub fn excluded_below(&self, path: &Path, root: &Path) -> bool {
+        let root = absolute_path(root);
+        let mut path = absolute_path(path);
+        while path.starts_with(&root) && path != root {
+            if self.excluded(&path) {
+                return true
+            }
+            path.pop();
+        }
+        false
+    }
+
+    // The files to check for the given paths.  Directories are walked
+    // recursively, in sorted order, keeping the files whose basename matches
+    // the filename patterns.  Paths which are not directories are always
+    // checked unless they are excluded.
+    pub fn find(&self, paths: &[String]) -> Vec<String> {
+        let mut files = Vec::new();
+        for path in paths {
+            if Path::new(path).is_dir() {
+                self.walk(Path::new(path), &mut files);
+            } else if !self.excluded(Path::new(path)) {
+                files.push(path.clone());
+            }
+        }
+        files
+    }
+
+    fn walk(&self, directory: &Path, files: &mut Vec<String>) {
+        if self.excluded(directory) {
+            return
+        }
+        let mut entries: Vec<(PathBuf, bool)> = match fs::read_dir(directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                // Like os.walk, symbolic links to directories are not followed.
+                .map(|entry| {
+                    let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+                    (entry.path(), is_dir)
+                })
+                .collect(),
+            Err(_) => return,
+        };
+        entries.sort();
+
+        let mut directories = Vec::new();
+        for (path, is_dir) in entries {
+            if is_dir {
+                directories.push(path);
+                continue
+            }
+            let basename = path.file_name().map_or(String::new(),
+                                                   |name| name.to_string_lossy().to_string());
+            if filename_match(&basename, &self.filename, true) && !self.excluded(&path) {
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+        for path in directories {
+            self.walk(&path, files);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_files {
+    use super::*;
+    use std::fs::File;
+
+    fn temporary_tree(name: &str, files: &[&str]) -> PathBuf {
+        let root = env::temp_dir().join(format!("rpycodestyle-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create(path).unwrap();
+        }
+        root
+    }
+
+    fn relative(root: &Path, files: Vec<String>) -> Vec<String> {
+        let prefix = format!("{}/", root.to_string_lossy());
+        files.iter().map(|file| file.trim_start_matches(prefix.as_str()).to_string()).collect()
+    }
+
+    #[test]
+    fn fnmatch_patterns() {
+        assert!(fnmatch("foo.py", "*.py"));
+        assert!(!fnmatch("foo.pyc", "*.py"));
+        assert!(fnmatch("a/b/foo.py", "*.py"));
+        assert!(fnmatch("foo.py", "fo?.py"));
+        assert!(fnmatch("foo1.py", "foo[0-9].py"));
+        assert!(!fnmatch("foo1.py", "foo[!0-9].py"));
+        assert!(fnmatch("foo[.py", "foo[.py"));
+        assert!(fnmatch("a.egg", "*.egg"));
+    }
+
+    #[test]
+    fn filename_match_default() {
+        assert!(filename_match("foo.py", &[], true));
+        assert!(!filename_match("foo.py", &["*.txt".to_string()], true));
+    }
+
+    #[test]
+    fn find_files_walks_directories_in_order() {
+        let root = temporary_tree("walk", &["b.py", "a.py", "c.txt", "sub/d.py",
+                                            ".git/e.py", "build/f.py", "x.egg/g.py"]);
+        let finder = FileFinder::new(&Options::default());
+        let files = finder.find(&[root.to_string_lossy().to_string()]);
+        assert_eq!(relative(&root, files),
+                   vec!["a.py", "b.py", "build/f.py", "sub/d.py"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn find_files_exclude_and_filename() {
+        let root = temporary_tree("exclude", &["a.py", "b.pyi", "sub/a.py", "build/c.py"]);
+        let mut options = Options::default();
+        options.set("filename", "*.py,*.pyi").unwrap();
+        let build = format!("{}/build", root.to_string_lossy());
+        options.set("exclude", &format!("a.py,{}", build)).unwrap();
+        let finder = FileFinder::new(&options);
+        let files = finder.find(&[root.to_string_lossy().to_string()]);
+        assert_eq!(relative(&root, files), vec!["b.pyi"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn excluded_below_checks_parent_directories() {
+        let mut options = Options::default();
+        options.set("exclude", "build").unwrap();
+        let finder = FileFinder::new(&options);
+        assert!(finder.excluded_below(Path::new("/p/build/x/a.py"), Path::new("/p")));
+        assert!(!finder.excluded_below(Path::new("/build/p/a.py"), Path::new("/build/p")));
+    }
+
+    #[test]
+    fn find_files_explicit_paths() {
+        let finder = FileFinder::new(&Options::default());
+        let paths = vec!["foo.txt".to_string(), ".git".to_string(), "x.egg".to_string()];
+        assert_eq!(finder.find(&paths), vec!["foo.txt"]);
+    }
+}
@@ -0,0 +1,509 @@
+use std::io;
+use std::io::Write;
+
+use diagnostic::Diagnostic;
+use registry::Rule;
+
+// Columns in the written formats start at 1, end columns are exclusive.
+
+// A checked path and the diagnostics reported for it.
+pub type FileResult = (String, Vec<Diagnostic>);
+
+// The named templates of the line formats, other templates are given as
+// they are with --format.
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("default", "%(path)s:%(row)d:%(col)d: %(code)s %(text)s"),
+    ("pylint", "%(path)s:%(row)d: [%(code)s] %(text)s"),
+];
+
+#[derive(PartialEq, Debug, Clone)]
+enum Part {
+    Text(String),
+    // A "%(name)<flags><width><conversion>" field.
+    Field { name: String, left: bool, zero: bool, width: usize, conversion: char },
+}
+
+// A line format in the style of Python's "%" formatting with named fields:
+// path, row, col, code and text.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let error = |message: &str| format!("invalid format '{}': {}", template, message);
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(char) = chars.next() {
+            if char != '%' {
+                text.push(char);
+                continue
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                text.push('%');
+                continue
+            }
+            if chars.next() != Some('(') {
+                return Err(error("fields must be named, as in %(path)s"))
+            }
+            let name: String = chars.by_ref().take_while(|&char| char != ')').collect();
+            if !["path", "row", "col", "code", "text"].contains(&name.as_str()) {
+                return Err(error(&format!("unknown field '{}'", name)))
+            }
+            let (mut left, mut zero) = (false, false);
+            while let Some(&flag) = chars.peek().filter(|&&char| char == '-' || char == '0') {
+                left |= flag == '-';
+                zero |= flag == '0';
+                chars.next();
+            }
+            let mut width = 0;
+            while let Some(digit) = chars.peek().and_then(|char| char.to_digit(10)) {
+                width = width * 10 + digit as usize;
+                chars.next();
+            }
+            let conversion = match chars.next() {
+                Some('s') => 's',
+                Some('d') if name == "row" || name == "col" => 'd',
+                Some('d') => return Err(error(&format!("%d needs a number, {} is text", name))),
+                _ => return Err(error(&format!("unsupported conversion for {}", name))),
+            };
+            if !text.is_empty() {
+                parts.push(Part::Text(text.split_off(0)));
+            }
+            parts.push(Part::Field { name, left, zero, width, conversion });
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+
+    // The template of a --format value, named or given as it is.
+    pub fn from_format(format: &str) -> Result<Template, String> {
+        let named = TEMPLATES.iter().find(|&&(name, _)| name == format.to_lowercase());
+        Template::parse(named.map_or(format, |&(_, template)| template))
+    }
+
+    pub fn render(&self, path: &str, diagnostic: &Diagnostic) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            let (name, left, zero, width, conversion) = match *part {
+                Part::Text(ref text) => {
+                    line.push_str(text);
+                    continue
+                }
+                Part::Field { ref name, left, zero, width, conversion } =>
+                    (name, left, zero, width, conversion),
+            };
+            let value = match name.as_str() {
+                "path" => path.to_string(),
+                "row" => diagnostic.line.to_string(),
+                "col" => (diagnostic.column + 1).to_string(),
+                "code" => diagnostic.code.to_string(),
+                _ => diagnostic.message.clone(),
+            };
+            let value = match (left, zero && conversion == 'd') {
+                (true, _) => format!("{:<width$}", value, width = width),
+                (false, true) => format!("{:0>width$}", value, width = width),
+                (false, false) => format!("{:>width$}", value, width = width),
+            };
+            line.push_str(&value);
+        }
+        line
+    }
+}
+
+pub fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for char in text.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            char if (char as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", char as u32)),
+            char => escaped.push(char),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+pub fn write_json(out: &mut dyn Write,
+                  results: &[FileResult]) -> io::Result<()> {
+//    An array with an object per diagnostic:
+//
+//    [
+//      {"path": "a.py", "line": 1, "column": 80, "end_column": 91, "code": "E501",
+//       "message": "line too long (90 > 79 characters)", "severity": "error"}
+//    ]
+    let diagnostics: Vec<(&String, &Diagnostic)> = results.iter()
+        .flat_map(|(path, diagnostics)| diagnostics.iter().map(move |diagnostic| (path, diagnostic)))
+        .collect();
+    if diagnostics.is_empty() {
+        return writeln!(out, "[]")
+    }
+    writeln!(out, "[")?;
+    for (index, &(path, diagnostic)) in diagnostics.iter().enumerate() {
+        let separator = if index + 1 < diagnostics.len() { "," } else { "" };
+        writeln!(out, "  {{\"path\": {}, \"line\": {}, \"column\": {}, \"end_column\": {}, \
+                       \"code\": {}, \"message\": {}, \"severity\": {}}}{}",
+                 json_string(path), diagnostic.line, diagnostic.column + 1,
+                 diagnostic.end_column + 1, json_string(diagnostic.code.as_str()),
+                 json_string(&diagnostic.message), json_string(diagnostic.severity.as_str()),
+                 separator)?;
+    }
+    writeln!(out, "]")
+}
+
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for char in text.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\t' => escaped.push(char),
+            // Other control characters are not allowed in XML 1.0.
+            char if (char as u32) < 0x20 => escaped.push_str(&format!("&#x{:X};", char as u32)),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+pub fn write_junit(out: &mut dyn Write,
+                   results: &[FileResult]) -> io::Result<()> {
+//    A test suite with a test case per checked file, the files with
+//    diagnostics fail:
+//
+//    <testsuite name="rpycodestyle" tests="2" failures="1" errors="0">
+//      <testcase classname="rpycodestyle" name="a.py">
+//        <failure type="rpycodestyle" message="1 diagnostic">a.py:1:7: E231 ...</failure>
+//      </testcase>
+//      <testcase classname="rpycodestyle" name="b.py"/>
+//    </testsuite>
+    let failures = results.iter().filter(|(_, diagnostics)| !diagnostics.is_empty()).count();
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<testsuite name=\"rpycodestyle\" tests=\"{}\" failures=\"{}\" errors=\"0\">",
+             results.len(), failures)?;
+    for (path, diagnostics) in results {
+        if diagnostics.is_empty() {
+            writeln!(out, "  <testcase classname=\"rpycodestyle\" name=\"{}\"/>",
+                     xml_escape(path))?;
+            continue
+        }
+        writeln!(out, "  <testcase classname=\"rpycodestyle\" name=\"{}\">", xml_escape(path))?;
+        let message = match diagnostics.len() {
+            1 => "1 diagnostic".to_string(),
+            count => format!("{} diagnostics", count),
+        };
+        let lines: Vec<String> = diagnostics.iter()
+            .map(|diagnostic| format!("{}:{}:{}: {}", path, diagnostic.line,
+                                      diagnostic.column + 1, diagnostic))
+            .collect();
+        writeln!(out, "    <failure type=\"rpycodestyle\" message=\"{}\">{}</failure>",
+                 message, xml_escape(&lines.join("\n")))?;
+        writeln!(out, "  </testcase>")?;
+    }
+    writeln!(out, "</testsuite>")
+}
+
+pub fn write_checkstyle(out: &mut dyn Write,
+                        results: &[FileResult]) -> io::Result<()> {
+//    A file element per checked file, with an error element per diagnostic:
+//
+//    <checkstyle version="4.3">
+//      <file name="a.py">
+//        <error line="1" column="7" severity="error"
+//               message="missing whitespace after ," source="rpycodestyle.E231"/>
+//      </file>
+//    </checkstyle>
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<checkstyle version=\"4.3\">")?;
+    for (path, diagnostics) in results {
+        if diagnostics.is_empty() {
+            writeln!(out, "  <file name=\"{}\"/>", xml_escape(path))?;
+            continue
+        }
+        writeln!(out, "  <file name=\"{}\">", xml_escape(path))?;
+        for diagnostic in diagnostics {
+            writeln!(out, "    <error line=\"{}\" column=\"{}\" severity=\"{}\" message=\"{}\" \
+                           source=\"rpycodestyle.{}\"/>",
+                     diagnostic.line, diagnostic.column + 1, diagnostic.severity.as_str(),
+                     xml_escape(&diagnostic.message), diagnostic.code)?;
+        }
+        writeln!(out, "  </file>")?;
+    }
+    writeln!(out, "</checkstyle>")
+}
+
+// Escape the data of a GitHub Actions workflow command, or one of its
+// properties, where ":" and "," are separators too.
+pub fn github_escape(text: &str, property: bool) -> String {
+    let mut escaped = String::new();
+    for char in text.chars() {
+        match char {
+            '%' => escaped.push_str("%25"),
+            '\r' => escaped.push_str("%0D"),
+            '\n' => escaped.push_str("%0A"),
+            ':' if property => escaped.push_str("%3A"),
+            ',' if property => escaped.push_str("%2C"),
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+// A relative URI reference for a path, as SARIF artifact locations want.
+fn uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    let mut uri = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' =>
+                uri.push(byte as char),
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+pub fn write_sarif(out: &mut dyn Write, results: &[FileResult],
+                   rules: &[Rule]) -> io::Result<()> {
+//    A SARIF 2.1.0 log with a single run.  Each rule carries the doc of the
+//    check emitting it, each result points to its rule and to the region
+//    of the diagnostic.
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"$schema\": \"https://json.schemastore.org/sarif-2.1.0.json\",")?;
+    writeln!(out, "  \"version\": \"2.1.0\",")?;
+    writeln!(out, "  \"runs\": [")?;
+    writeln!(out, "    {{")?;
+    writeln!(out, "      \"tool\": {{")?;
+    writeln!(out, "        \"driver\": {{")?;
+    writeln!(out, "          \"name\": \"rpycodestyle\",")?;
+    writeln!(out, "          \"version\": {},", json_string(env!("CARGO_PKG_VERSION")))?;
+    writeln!(out, "          \"rules\": [")?;
+    for (index, rule) in rules.iter().enumerate() {
+        let separator = if index + 1 < rules.len() { "," } else { "" };
+        let summary = rule.doc.split("\n\n").next().unwrap_or("").replace('\n', " ");
+        writeln!(out, "            {{")?;
+        writeln!(out, "              \"id\": {},", json_string(rule.code.as_str()))?;
+        writeln!(out, "              \"name\": {},", json_string(rule.name))?;
+        writeln!(out, "              \"shortDescription\": {{\"text\": {}}},",
+                 json_string(&summary))?;
+        writeln!(out, "              \"fullDescription\": {{\"text\": {}}},",
+                 json_string(rule.doc))?;
+        writeln!(out, "              \"defaultConfiguration\": {{\"level\": {}}}",
+                 json_string(rule.code.severity().as_str()))?;
+        writeln!(out, "            }}{}", separator)?;
+    }
+    writeln!(out, "          ]")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "      }},")?;
+
+    let diagnostics: Vec<(&String, &Diagnostic)> = results.iter()
+        .flat_map(|(path, diagnostics)| diagnostics.iter().map(move |diagnostic| (path, diagnostic)))
+        .collect();
+    if diagnostics.is_empty() {
+        writeln!(out, "      \"results\": []")?;
+    } else {
+        writeln!(out, "      \"results\": [")?;
+        for (index, &(path, diagnostic)) in diagnostics.iter().enumerate() {
+            let separator = if index + 1 < diagnostics.len() { "," } else { "" };
+            writeln!(out, "        {{")?;
+            writeln!(out, "          \"ruleId\": {},", json_string(diagnostic.code.as_str()))?;
+            // Codes without a registered check, such as E902, have no rule.
+            if let Some(rule_index) = rules.iter().position(|rule| rule.code == diagnostic.code) {
+                writeln!(out, "          \"ruleIndex\": {},", rule_index)?;
+            }
+            writeln!(out, "          \"level\": {},", json_string(diagnostic.severity.as_str()))?;
+            writeln!(out, "          \"message\": {{\"text\": {}}},",
+                     json_string(&diagnostic.message))?;
+            writeln!(out, "          \"locations\": [")?;
+            writeln!(out, "            {{")?;
+            writeln!(out, "              \"physicalLocation\": {{")?;
+            writeln!(out, "                \"artifactLocation\": {{\"uri\": {}}},",
+                     json_string(&uri(path)))?;
+            writeln!(out, "                \"region\": {{\"startLine\": {}, \"startColumn\": {}, \
+                           \"endLine\": {}, \"endColumn\": {}}}",
+                     diagnostic.line, diagnostic.column + 1, diagnostic.line,
+                     diagnostic.end_column + 1)?;
+            writeln!(out, "              }}")?;
+            writeln!(out, "            }}")?;
+            writeln!(out, "          ]")?;
+            writeln!(out, "        }}{}", separator)?;
+        }
+        writeln!(out, "      ]")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod test_formats {
+    use super::*;
+    use diagnostic::Code;
+
+    fn results() -> Vec<FileResult> {
+        vec![
+            ("a.py".to_string(), vec![
+                Diagnostic::new(Code::new("W291"), 2, 5, "trailing whitespace".to_string())
+                    .with_end_column(7),
+            ]),
+            ("b \"c\".py".to_string(), vec![]),
+            ("d.py".to_string(), vec![
+                Diagnostic::new(Code::new("E231"), 1, 6, "missing whitespace after ,".to_string()),
+            ]),
+        ]
+    }
+
+    fn written(write: fn(&mut dyn Write, &[FileResult]) -> io::Result<()>,
+               results: &[FileResult]) -> String {
+        let mut out = Vec::new();
+        write(&mut out, results).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn template_presets() {
+        let diagnostic = &results()[0].1[0];
+        assert_eq!(Template::from_format("default").unwrap().render("a.py", diagnostic),
+                   "a.py:2:6: W291 trailing whitespace");
+        assert_eq!(Template::from_format("Pylint").unwrap().render("a.py", diagnostic),
+                   "a.py:2: [W291] trailing whitespace");
+    }
+
+    #[test]
+    fn template_fields() {
+        let diagnostic = &results()[0].1[0];
+        let template = Template::parse("%(path)s|%(row)d|%(col)d|%(code)s|%(text)s").unwrap();
+        assert_eq!(template.render("a.py", diagnostic), "a.py|2|6|W291|trailing whitespace");
+        let template = Template::parse("%(row)03d:%(col)-3d:%(code)6s 100%%").unwrap();
+        assert_eq!(template.render("a.py", diagnostic), "002:6  :  W291 100%");
+    }
+
+    #[test]
+    fn template_errors() {
+        assert_eq!(Template::parse("%(file)s"),
+                   Err("invalid format '%(file)s': unknown field 'file'".to_string()));
+        assert_eq!(Template::parse("%(path)d"),
+                   Err("invalid format '%(path)d': %d needs a number, path is text".to_string()));
+        assert_eq!(Template::parse("%s"),
+                   Err("invalid format '%s': fields must be named, as in %(path)s".to_string()));
+        assert_eq!(Template::parse("%(code)x"),
+                   Err("invalid format '%(code)x': unsupported conversion for code".to_string()));
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("a \"b\" \\ \n\t\u{1}é"), "\"a \\\"b\\\" \\\\ \\n\\t\\u0001é\"");
+    }
+
+    #[test]
+    fn write_json_diagnostics() {
+        assert_eq!(written(write_json, &results()), "[\n\
+            \x20 {\"path\": \"a.py\", \"line\": 2, \"column\": 6, \"end_column\": 8, \
+            \"code\": \"W291\", \"message\": \"trailing whitespace\", \"severity\": \"warning\"},\n\
+            \x20 {\"path\": \"d.py\", \"line\": 1, \"column\": 7, \"end_column\": 8, \
+            \"code\": \"E231\", \"message\": \"missing whitespace after ,\", \
+            \"severity\": \"error\"}\n]\n");
+    }
+
+    #[test]
+    fn write_json_empty() {
+        assert_eq!(written(write_json, &[]), "[]\n");
+    }
+
+    #[test]
+    fn xml_escape_special_characters() {
+        assert_eq!(xml_escape("a<b> & \"c\" 'd'\u{1}"),
+                   "a&lt;b&gt; &amp; &quot;c&quot; &apos;d&apos;&#x1;");
+    }
+
+    #[test]
+    fn write_junit_test_cases() {
+        assert_eq!(written(write_junit, &results()), "\
+            <?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <testsuite name=\"rpycodestyle\" tests=\"3\" failures=\"2\" errors=\"0\">\n\
+            \x20 <testcase classname=\"rpycodestyle\" name=\"a.py\">\n\
+            \x20   <failure type=\"rpycodestyle\" message=\"1 diagnostic\">\
+            a.py:2:6: W291 trailing whitespace</failure>\n\
+            \x20 </testcase>\n\
+            \x20 <testcase classname=\"rpycodestyle\" name=\"b &quot;c&quot;.py\"/>\n\
+            \x20 <testcase classname=\"rpycodestyle\" name=\"d.py\">\n\
+            \x20   <failure type=\"rpycodestyle\" message=\"1 diagnostic\">\
+            d.py:1:7: E231 missing whitespace after ,</failure>\n\
+            \x20 </testcase>\n\
+            </testsuite>\n");
+    }
+
+    #[test]
+    fn write_checkstyle_files() {
+        assert_eq!(written(write_checkstyle, &results()), "\
+            <?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <checkstyle version=\"4.3\">\n\
+            \x20 <file name=\"a.py\">\n\
+            \x20   <error line=\"2\" column=\"6\" severity=\"warning\" \
+            message=\"trailing whitespace\" source=\"rpycodestyle.W291\"/>\n\
+            \x20 </file>\n\
+            \x20 <file name=\"b &quot;c&quot;.py\"/>\n\
+            \x20 <file name=\"d.py\">\n\
+            \x20   <error line=\"1\" column=\"7\" severity=\"error\" \
+            message=\"missing whitespace after ,\" source=\"rpycodestyle.E231\"/>\n\
+            \x20 </file>\n\
+            </checkstyle>\n");
+    }
+
+    #[test]
+    fn github_escape_data_and_properties() {
+        assert_eq!(github_escape("100% a:b,c\n", false), "100%25 a:b,c%0A");
+        assert_eq!(github_escape("100% a:b,c\n", true), "100%25 a%3Ab%2Cc%0A");
+    }
+
+    #[test]
+    fn uri_escapes() {
+        assert_eq!(uri("./a b/c%.py"), "a%20b/c%25.py");
+        assert_eq!(uri("src\\é.py"), "src/%C3%A9.py");
+    }
+
+    #[test]
+    fn write_sarif_rules_and_results() {
+        let rules = [
+            Rule { code: Code::new("E231"), name: "missing_whitespace",
+                   doc: "Each comma should be\nfollowed by whitespace.\n\nOkay: [a, b]" },
+        ];
+        let mut out = Vec::new();
+        write_sarif(&mut out, &results(), &rules).unwrap();
+        let sarif = String::from_utf8(out).unwrap();
+        assert!(sarif.contains("\"version\": \"2.1.0\""));
+        assert!(sarif.contains("\"id\": \"E231\",\n              \"name\": \"missing_whitespace\""));
+        assert!(sarif.contains("\"shortDescription\": {\"text\": \"Each comma should be followed \
+                                by whitespace.\"}"));
+        assert!(sarif.contains("\"fullDescription\": {\"text\": \"Each comma should be\\n\
+                                followed by whitespace.\\n\\nOkay: [a, b]\"}"));
+        assert!(sarif.contains("\"defaultConfiguration\": {\"level\": \"error\"}"));
+        assert!(sarif.contains("\"ruleId\": \"W291\",\n          \"level\": \"warning\""));
+        assert!(sarif.contains("\"ruleId\": \"E231\",\n          \"ruleIndex\": 0,"));
+        assert!(sarif.contains("\"artifactLocation\": {\"uri\": \"a.py\"},\n\x20               \
+                                \"region\": {\"startLine\": 2, \"startColumn\": 6, \
+                                \"endLine\": 2, \"endColumn\": 8}"));
+    }
+
+    #[test]
+    fn write_sarif_empty() {
+        let mut out = Vec::new();
+        write_sarif(&mut out, &[], &[]).unwrap();
+        let sarif = String::from_utf8(out).unwrap();
+        assert!(sarif.contains("\"rules\": [\n          ]"));
+        assert!(sarif.contains("\"results\": []"));
+    }
+}
@@ -1,16 +1,36 @@
 extern crate regex;
+extern crate toml;
 use regex::Regex;
-
-#[derive(PartialEq, Debug, Clone)]
-pub struct Error {
-    column_number: usize,
-    error_message: String,
-}
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub mod tokenizer;
+pub mod logical;
+pub mod diagnostic;
+pub mod registry;
+pub mod options;
+pub mod cli;
+pub mod report;
+pub mod formats;
+pub mod files;
+pub mod config;
+pub mod noqa;
+use tokenizer::{tokenize, Token, TokenType};
+use logical::{build_logical_lines, LogicalLine};
+use noqa::Noqa;
+pub use diagnostic::{Code, Diagnostic, Severity};
+pub use options::Options;
+pub use registry::{LogicalLineCheck, LogicalLineContext, LogicalLineFn, PhysicalLineCheck,
+                   PhysicalLineContext, PhysicalLineFn, Registry, Rule, TokenCheck,
+                   TokenContext, TokenFn};
 
 fn get_keywords() -> Vec<&'static str> {
 //    python keywords with print added and True, False and
 //    None removed
-    vec!["and", "as", "assert", "break", "class", "continue",
+    vec!["and", "as", "assert", "async", "await", "break", "class", "continue",
          "def", "del", "elif", "else", "except",
          "finally", "for", "from", "global",
          "if", "import", "in", "is", "lambda", "nonlocal",
@@ -18,95 +38,280 @@ fn get_keywords() -> Vec<&'static str> {
          "try", "while", "with", "yield", "print"]
 }
 
-fn calculate_indent_level(line: &str, indent_char: char) -> usize {
-    for (index, char) in line.chars().enumerate() {
-        if char != indent_char {
-            return index
+fn expand_indent(line: &str) -> usize {
+//    Return the amount of indentation.
+//
+//    Tabs are expanded to the next multiple of 8.
+    let line = line.trim_end_matches(['\n', '\r']);
+    if !line.contains('\t') {
+        return line.chars().count() - line.trim_start().chars().count()
+    }
+    let mut result = 0;
+    for char in line.chars() {
+        match char {
+            '\t' => result = result / 8 * 8 + 8,
+            ' ' => result += 1,
+            _ => break,
         }
     }
-    return 0
+    result
 }
 
-pub fn reporting(path: &String, line_number: usize, line: &str, total_lines: usize,
-                 previous_line: &str, num_blank_lines: usize) {
-    let errors = checker(line, line_number, total_lines, previous_line,
-                         num_blank_lines);
-    for error_option in errors {
-        if error_option != None {
-            let error = error_option.unwrap();
-            println!("./{}:{}:{} {}", path, line_number, error.column_number,
-                     error.error_message);
-        }
-    }
+// The indent character of a file is the first character of its first
+// indented line.
+fn detect_indent_char(lines: &[&str]) -> Option<char> {
+    lines.iter()
+        .filter_map(|line| line.chars().next())
+        .find(|&char| char == ' ' || char == '\t')
 }
 
+// Columns are counted in characters, convert a byte offset in text, as
+// returned by str::find and regex matches, to one.
+fn char_offset(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].chars().count()
+}
 
-fn checker(line: &str, line_number: usize, total_lines: usize,
-           previous_line: &str, num_blank_lines: usize) ->  Vec<Option<Error>> {
+fn is_operator(token: &Token, operators: &[&str]) -> bool {
+    token.token_type == TokenType::Op && operators.contains(&token.text.as_str())
+}
+
+pub fn check_file(path: &Path, options: &Options) -> Result<Vec<Diagnostic>, io::Error> {
+    let source = fs::read_to_string(path)?;
+    Ok(check_source(&source, &options.for_path(path)))
+}
+
+pub fn check_source(source: &str, options: &Options) -> Vec<Diagnostic> {
+    let mut registry = Registry::default();
+    registry.retain_enabled(|code| !options.ignore_code(code));
+    check_source_with(source, options, &registry)
+}
+
+pub fn check_source_with(source: &str, options: &Options,
+                         registry: &Registry) -> Vec<Diagnostic> {
     let mut errors = Vec::new();
-    //    Config variables
-    let max_length = 120;
-    let indent_char = ' ';
-    let indent_level = calculate_indent_level(line, indent_char);
-    let previous_line_indent_level = calculate_indent_level(previous_line, indent_char);
-
-
-    errors.push(maximum_line_length(line, max_length));
-    errors.push(tabs_or_spaces(line, indent_char));
-    errors.push(tabs_obsolete(line));
-    errors.push(trailing_whitespace(line));
-    errors.push(trailing_blank_lines(line, line_number, total_lines));
-    errors.push(blank_lines(line, line_number, previous_line, num_blank_lines));
-    errors.extend(extraneous_whitespace(line).iter().cloned());
-    errors.extend(whitespace_around_keywords(line).iter().cloned());
-    errors.push(missing_whitespace_after_import_keyword(line));
-    errors.extend(missing_whitespace(line).iter().cloned());
-    errors.push(indentation(line, previous_line, indent_level, previous_line_indent_level));
-    errors.extend(whitespace_around_operator(line).iter().cloned());
-    errors.extend(whitespace_around_comma(line).iter().cloned());
-    errors.push(imports_on_separate_lines(line));
+
+    let physical_lines: Vec<&str> = source.split_inclusive('\n').collect();
+    let total_lines = physical_lines.len();
+    let indent_char = detect_indent_char(&physical_lines).unwrap_or(options.indent_char);
+    let noqa: Vec<Option<Noqa>> = physical_lines.iter().map(|line| Noqa::parse(line)).collect();
+    let suppressed = |row: usize, error: &Diagnostic| {
+        noqa.get(row - 1).is_some_and(|noqa| noqa.as_ref().is_some_and(|noqa| {
+            noqa.suppresses(error.code)
+        }))
+    };
+    for (index, physical_line) in physical_lines.iter().enumerate() {
+        let context = PhysicalLineContext {
+            line: physical_line.trim_end_matches(['\n', '\r']),
+            physical_line,
+            line_number: index + 1,
+            total_lines,
+            indent_char,
+            options,
+        };
+        for check in &registry.physical_line_checks {
+            errors.extend(check.check(&context).into_iter()
+                          .filter(|error| !suppressed(error.line, error)));
+        }
+    }
+
+    let tokens = match tokenize(source) {
+        Ok(tokens) => Some(tokens),
+        Err(error) => {
+            let message = format!("TokenError: {}", error.message);
+            errors.push(Diagnostic::new(Code::new("E902"), error.line, error.column,
+                                        message));
+            None
+        }
+    };
+    // The logical line and token checks need the tokens.
+    if let Some(tokens) = tokens {
+        let mut previous_logical = String::new();
+        let mut previous_unindented_logical = String::new();
+        let mut previous_indent_level = 0;
+        for logical_line in build_logical_lines(&tokens) {
+            let start_line = logical_line.physical_line();
+            let start_col = logical_line.start().1;
+            let indent = start_line.chars().take(start_col).collect::<String>();
+            let indent_level = expand_indent(&indent);
+            let context = LogicalLineContext {
+                logical_line: &logical_line,
+                previous_logical: &previous_logical,
+                previous_unindented_logical: &previous_unindented_logical,
+                indent_level,
+                previous_indent_level,
+                lines: &physical_lines,
+                indent_char,
+                options,
+            };
+            // A noqa comment on any of its physical lines applies to the whole
+            // logical line.
+            let (first_row, last_row) = logical_line.rows();
+            for check in &registry.logical_line_checks {
+                errors.extend(check.check(&context).into_iter()
+                              .filter(|error| !(first_row..=last_row)
+                                      .any(|row| suppressed(row, error))));
+            }
+
+            if !logical_line.text.is_empty() {
+                previous_indent_level = indent_level;
+                if indent_level == 0 {
+                    previous_unindented_logical = logical_line.text.clone();
+                }
+                previous_logical = logical_line.text;
+            }
+        }
+
+        let context = TokenContext {
+            tokens: &tokens,
+            lines: &physical_lines,
+            options,
+        };
+        for check in &registry.token_checks {
+            errors.extend(check.check(&context).into_iter()
+                          .filter(|error| !suppressed(error.line, error)));
+        }
+    }
+
+    errors.retain(|error| !options.ignore_code(error.code));
+    errors.sort_by_key(|error| (error.line, error.column));
     errors
 }
 
+fn register_builtin_checks(registry: &mut Registry) {
+    registry.register_physical_line(PhysicalLineFn::new(
+        "tabs_or_spaces", &["E101"],
+        |context| tabs_or_spaces(context.line, context.line_number,
+                                 context.indent_char).into_iter().collect())
+        .with_doc(TABS_OR_SPACES));
+    registry.register_physical_line(PhysicalLineFn::new(
+        "tabs_obsolete", &["W191"],
+        |context| tabs_obsolete(context.line, context.line_number).into_iter().collect())
+        .with_doc(TABS_OBSOLETE));
+    registry.register_physical_line(PhysicalLineFn::new(
+        "trailing_whitespace", &["W291", "W293"],
+        |context| trailing_whitespace(context.line, context.line_number)
+            .into_iter().collect())
+        .with_doc(TRAILING_WHITESPACE));
+    registry.register_physical_line(PhysicalLineFn::new(
+        "trailing_blank_lines", &["W292", "W391"],
+        |context| trailing_blank_lines(context.physical_line, context.line_number,
+                                       context.total_lines).into_iter().collect())
+        .with_doc(TRAILING_BLANK_LINES));
+    registry.register_physical_line(PhysicalLineFn::new(
+        "maximum_line_length", &["E501"],
+        |context| maximum_line_length(context.line, context.line_number,
+                                      context.options.max_line_length).into_iter().collect())
+        .with_doc(MAXIMUM_LINE_LENGTH));
+
+    registry.register_logical_line(LogicalLineFn::new(
+        "blank_lines", &["E301", "E302", "E303", "E304", "E305", "E306"],
+        |context| blank_lines(context.logical_line, context.previous_logical,
+                              context.previous_unindented_logical, context.indent_level,
+                              context.previous_indent_level, context.lines)
+            .into_iter().collect())
+        .with_doc(BLANK_LINES));
+    registry.register_logical_line(LogicalLineFn::new(
+        "extraneous_whitespace", &["E201", "E202", "E203"],
+        |context| extraneous_whitespace(context.logical_line).into_iter().flatten().collect())
+        .with_doc(EXTRANEOUS_WHITESPACE));
+    registry.register_logical_line(LogicalLineFn::new(
+        "whitespace_around_keywords", &["E271", "E272", "E273", "E274"],
+        |context| whitespace_around_keywords(context.logical_line)
+            .into_iter().flatten().collect())
+        .with_doc(WHITESPACE_AROUND_KEYWORDS));
+    registry.register_logical_line(LogicalLineFn::new(
+        "missing_whitespace_after_import_keyword", &["E275"],
+        |context| missing_whitespace_after_import_keyword(context.logical_line)
+            .into_iter().collect())
+        .with_doc(MISSING_WHITESPACE_AFTER_IMPORT_KEYWORD));
+    registry.register_logical_line(LogicalLineFn::new(
+        "missing_whitespace", &["E231"],
+        |context| missing_whitespace(context.logical_line).into_iter().flatten().collect())
+        .with_doc(MISSING_WHITESPACE));
+    registry.register_logical_line(LogicalLineFn::new(
+        "indentation", &["E111", "E112", "E113", "E114", "E115", "E116"],
+        |context| indentation(context.logical_line, context.previous_logical,
+                              context.indent_level, context.previous_indent_level)
+            .into_iter().collect())
+        .with_doc(INDENTATION));
+    registry.register_logical_line(LogicalLineFn::new(
+        "continued_indentation",
+        &["E121", "E122", "E123", "E124", "E125", "E126", "E127", "E128", "E129", "E131",
+          "E133"],
+        |context| continued_indentation(context.logical_line, context.indent_char,
+                                        context.options.hang_closing))
+        .with_doc(CONTINUED_INDENTATION));
+    registry.register_logical_line(LogicalLineFn::new(
+        "whitespace_around_operator", &["E221", "E222", "E223", "E224"],
+        |context| whitespace_around_operator(context.logical_line)
+            .into_iter().flatten().collect())
+        .with_doc(WHITESPACE_AROUND_OPERATOR));
+    registry.register_logical_line(LogicalLineFn::new(
+        "missing_whitespace_around_operator", &["E225", "E226", "E227", "E228"],
+        |context| missing_whitespace_around_operator(context.logical_line))
+        .with_doc(MISSING_WHITESPACE_AROUND_OPERATOR));
+    registry.register_logical_line(LogicalLineFn::new(
+        "whitespace_around_default_equals", &["E251", "E252"],
+        |context| whitespace_around_default_equals(context.logical_line))
+        .with_doc(WHITESPACE_AROUND_DEFAULT_EQUALS));
+    registry.register_logical_line(LogicalLineFn::new(
+        "whitespace_around_comma", &["E241", "E242"],
+        |context| whitespace_around_comma(context.logical_line)
+            .into_iter().flatten().collect())
+        .with_doc(WHITESPACE_AROUND_COMMA));
+    registry.register_logical_line(LogicalLineFn::new(
+        "whitespace_before_comment", &["E261", "E262", "E265", "E266"],
+        |context| whitespace_before_comment(context.logical_line))
+        .with_doc(WHITESPACE_BEFORE_COMMENT));
+    registry.register_logical_line(LogicalLineFn::new(
+        "imports_on_separate_lines", &["E401"],
+        |context| imports_on_separate_lines(context.logical_line).into_iter().collect())
+        .with_doc(IMPORTS_ON_SEPARATE_LINES));
+    registry.register_logical_line(LogicalLineFn::new(
+        "maximum_doc_length", &["W505"],
+        |context| maximum_doc_length(context.logical_line, context.options.max_doc_length))
+        .with_doc(MAXIMUM_DOC_LENGTH));
+}
+
 // Physical lines
-fn tabs_or_spaces(line: &str, indent_char: char) -> Option<Error> {
-//    Never mix tabs and spaces.
-//
-//    The most popular way of indenting Python is with spaces only.  The
-//    second-most popular way is with tabs only.  Code indented with a mixture
-//    of tabs and spaces should be converted to using spaces exclusively.  When
-//    invoking the Python command line interpreter with the -t option, it issues
-//    warnings about code that illegally mixes tabs and spaces.  When using -tt
-//    these warnings become errors.  These options are highly recommended!
-//
-//    Okay: if a == 0:\n        a = 1\n        b = 1
-//    E101: if a == 0:\n        a = 1\n\tb = 1
+const TABS_OR_SPACES: &str = r"Never mix tabs and spaces.
+
+The most popular way of indenting Python is with spaces only.  The
+second-most popular way is with tabs only.  Code indented with a mixture
+of tabs and spaces should be converted to using spaces exclusively.  When
+invoking the Python command line interpreter with the -t option, it issues
+warnings about code that illegally mixes tabs and spaces.  When using -tt
+these warnings become errors.  These options are highly recommended!
 
+Okay: if a == 0:\n        a = 1\n        b = 1
+E101: if a == 0:\n        a = 1\n\tb = 1";
 
+fn tabs_or_spaces(line: &str, line_number: usize, indent_char: char) -> Option<Diagnostic> {
     let re = Regex::new(r"([ \t]*)").unwrap();
     let indent = re.find(line).unwrap();
     for (offset, char) in indent.as_str().chars().enumerate() {
         if char != indent_char {
-            let error = Error {
-                column_number: offset,
-                error_message: "E101 indentation contains mixed spaces and tabs".to_string()
-            };
+            let error = Diagnostic::new(
+                Code::new("E101"), line_number, offset,
+                "indentation contains mixed spaces and tabs".to_string());
             return Some(error)
         }
     }
     None
 }
 
-fn tabs_obsolete(line: &str) -> Option<Error> {
+const TABS_OBSOLETE: &str = r"On new projects, spaces-only are strongly recommended over tabs.
+
+Okay: if True:\n    return
+W191: if True:\n\treturn";
+
+fn tabs_obsolete(line: &str, line_number: usize) -> Option<Diagnostic> {
     let re = Regex::new(r"([ \t]*)").unwrap();
     let indent = re.find(line).unwrap();
     if indent.as_str().contains('\t') {
-        let error_message = "W191 indentation contains tabs".to_string();
         let column_number = indent.as_str().find('\t').unwrap();
-        let error = Error {
-            error_message: error_message,
-            column_number: column_number,
-        };
+        let error = Diagnostic::new(Code::new("W191"), line_number, column_number,
+                                    "indentation contains tabs".to_string());
         Some(error)
     }
     else {
@@ -114,31 +319,29 @@ fn tabs_obsolete(line: &str) -> Option<Error> {
     }
 }
 
-fn trailing_whitespace(line: &str) -> Option<Error>{
-    //    Trailing whitespace is superfluous.
-    //
-    //    The warning returned varies on whether the line itself is blank, for easier
-    //    filtering for those who want to indent their blank lines.
-    //
-    //    Okay: spam(1)\n#
-    //    W291: spam(1) \n#
-    //    W293: class Foo(object):\n    \n    bang = 12
-    let stripped_line = line.trim_right();
+const TRAILING_WHITESPACE: &str = r"Trailing whitespace is superfluous.
+
+The warning returned varies on whether the line itself is blank, for easier
+filtering for those who want to indent their blank lines.
+
+Okay: spam(1)\n#
+W291: spam(1) \n#
+W293: class Foo(object):\n    \n    bang = 12";
+
+fn trailing_whitespace(line: &str, line_number: usize) -> Option<Diagnostic>{
+    let stripped_line = line.trim_end();
     if line != stripped_line {
         if !stripped_line.is_empty() {
-            let message = "W291 trailing whitespace".to_string();
-            let error = Error {
-                column_number: stripped_line.len(),
-                error_message: message
-            };
+            let message = "trailing whitespace".to_string();
+            let error = Diagnostic::new(Code::new("W291"), line_number,
+                                        stripped_line.chars().count(), message)
+                .with_end_column(line.chars().count());
             Some(error)
         }
         else {
-            let message = "W293 blank line contains whitespace".to_string();
-            let error = Error {
-                error_message: message,
-                column_number: 0,
-            };
+            let message = "blank line contains whitespace".to_string();
+            let error = Diagnostic::new(Code::new("W293"), line_number, 0, message)
+                .with_end_column(line.chars().count());
             Some(error)
         }
     }
@@ -147,54 +350,52 @@ fn trailing_whitespace(line: &str) -> Option<Error>{
     }
 }
 
+const TRAILING_BLANK_LINES: &str = r"Trailing blank lines are superfluous.
+
+Okay: spam(1)
+W391: spam(1)\n
+
+However the last line should end with a new line (warning W292).";
+
 fn trailing_blank_lines(line: &str, line_number: usize,
-                        total_lines: usize) -> Option<Error>{
-    //    Trailing blank lines are superfluous.
-    //
-    //    Okay: spam(1)
-    //    W391: spam(1)\n
-    //
-    //    However the last line should end with a new line (warning W292).
+                        total_lines: usize) -> Option<Diagnostic>{
     if line_number == total_lines {
-        let stripped_last_line = line.trim_right();
+        let stripped_last_line = line.trim_end();
         if stripped_last_line.is_empty() {
-            let error = Error{
-                error_message: "W391 blank line at end of file".to_string(),
-                column_number: 0
-            };
+            let error = Diagnostic::new(Code::new("W391"), line_number, 0,
+                                        "blank line at end of file".to_string());
             return Some(error)
         }
         if stripped_last_line == line {
-            let error = Error{
-                error_message: "W292 no newline at end of file".to_string(),
-                column_number: line.len()
-            };
+            let error = Diagnostic::new(Code::new("W292"), line_number,
+                                        line.chars().count(),
+                                        "no newline at end of file".to_string());
             return Some(error)
         }
-        return None
     }
-    return None
+    None
 }
 
-fn maximum_line_length(line: &str, max_line_length: usize) -> Option<Error> {
-//    Limit all lines to a maximum of 79 characters.
-//
-//    There are still many devices around that are limited to 80 character
-//    lines; plus, limiting windows to 80 characters makes it possible to have
-//    several windows side-by-side.  The default wrapping on such devices looks
-//    ugly.  Therefore, please limit all lines to a maximum of 79 characters.
-//    For flowing long blocks of text (docstrings or comments), limiting the
-//    length to 72 characters is recommended.
-//
-//    Reports error E501.
-    let length = line.len();
+const MAXIMUM_LINE_LENGTH: &str = r"Limit all lines to a maximum of 79 characters.
+
+There are still many devices around that are limited to 80 character
+lines; plus, limiting windows to 80 characters makes it possible to have
+several windows side-by-side.  The default wrapping on such devices looks
+ugly.  Therefore, please limit all lines to a maximum of 79 characters.
+For flowing long blocks of text (docstrings or comments), limiting the
+length to 72 characters is recommended.
+
+Reports error E501.";
+
+fn maximum_line_length(line: &str, line_number: usize,
+                       max_line_length: usize) -> Option<Diagnostic> {
+    let length = line.chars().count();
     if length > max_line_length {
-        let error_message = format!("{} ES501 line to long ({} > {} characters)",
-                                    max_line_length, length, max_line_length);
-        let error = Error{
-            error_message: error_message,
-            column_number: max_line_length
-        };
+        let message = format!("line too long ({} > {} characters)",
+                              length, max_line_length);
+        let error = Diagnostic::new(Code::new("E501"), line_number, max_line_length,
+                                    message)
+            .with_end_column(length);
         Some(error)
     }
     else {
@@ -202,75 +403,190 @@ fn maximum_line_length(line: &str, max_line_length: usize) -> Option<Error> {
     }
 }
 
-fn blank_lines(_line: &str, _line_number: usize, previous_line: &str,
-               num_blank_lines: usize) -> Option<Error> {
-    // Not implementing 306, 301, 302, 305
-    if previous_line.starts_with("@") {
-        let error = Error {
-            error_message: "E304 blank lines found after function decorator".to_string(),
-            column_number: 0,
+// Logical lines
+const BLANK_LINES: &str = r"Separate top-level function and class definitions with two blank lines.
+
+Method definitions inside a class are separated by a single blank line.
+
+Extra blank lines may be used (sparingly) to separate groups of related
+functions.  Blank lines may be omitted between a bunch of related
+one-liners (e.g. a set of dummy implementations).
+
+Use blank lines in functions, sparingly, to indicate logical sections.
+
+Okay: def a():\n    pass\n\n\ndef b():\n    pass
+Okay: def a():\n    pass\n\n\nasync def b():\n    pass
+Okay: def a():\n    pass\n\n\n# Foo\n# Bar\n\ndef b():\n    pass
+Okay: default = 1\nfoo = 1
+Okay: classify = 1\nfoo = 1
+
+E301: class Foo:\n    b = 0\n    def bar():\n        pass
+E302: def a():\n    pass\n\ndef b(n):\n    pass
+E302: def a():\n    pass\n\nasync def b(n):\n    pass
+E303: def a():\n    pass\n\n\n\ndef b(n):\n    pass
+E303: def a():\n\n\n\n    pass
+E304: @decorator\n\ndef a():\n    pass
+E305: def a():\n    pass\na()
+E306: def a():\n    x = 1\n    def b():\n        pass";
+
+// Blank lines expected before a top-level definition and before a method.
+const TOP_LEVEL_LINES: usize = 2;
+const METHOD_LINES: usize = 1;
+
+// The patterns of blank_lines, compiled on first use.
+static STARTSWITH_DEF_REGEX: OnceLock<Regex> = OnceLock::new();
+static STARTSWITH_TOP_LEVEL_REGEX: OnceLock<Regex> = OnceLock::new();
+static DOCSTRING_REGEX: OnceLock<Regex> = OnceLock::new();
+
+fn starts_with_def(line: &str) -> bool {
+    STARTSWITH_DEF_REGEX.get_or_init(|| Regex::new(r"^(async\s+def|def)\b").unwrap())
+        .is_match(line)
+}
+
+fn starts_with_top_level(line: &str) -> bool {
+    STARTSWITH_TOP_LEVEL_REGEX
+        .get_or_init(|| Regex::new(r"^(async\s+def\s+|def\s+|class\s+|@)").unwrap())
+        .is_match(line)
+}
+
+fn is_one_liner(logical_line: &str, indent_level: usize, lines: &[&str],
+                line_number: usize) -> bool {
+    if !starts_with_top_level(logical_line) {
+        return false
+    }
+    let mut line_index = line_number - 1;
+    let previous_indent = if line_index < 1 { 0 } else { expand_indent(lines[line_index - 1]) };
+    if previous_indent > indent_level {
+        return false
+    }
+    loop {
+        // invalid syntax: EOF while searching for def/class
+        let line = match lines.get(line_index) {
+            Some(line) => line.trim(),
+            None => return false,
         };
-        Some(error)
+        if !line.starts_with('@') && starts_with_top_level(line) {
+            break
+        }
+        line_index += 1;
     }
-    else if num_blank_lines > 2 {
-        let error = Error {
-            error_message: format!("E303 too many blank lines {}", num_blank_lines),
-            column_number: 0,
-        };
-        Some(error)
+    match lines[line_index + 1..].iter().find(|line| !line.trim().is_empty()) {
+        Some(next_line) => expand_indent(next_line) <= indent_level,
+        // line is last in the file
+        None => true,
     }
-    else {
-        None
+}
+
+fn blank_lines(logical_line: &LogicalLine, previous_logical: &str,
+               previous_unindented_logical: &str, indent_level: usize,
+               previous_indent_level: usize, lines: &[&str]) -> Option<Diagnostic> {
+    let blank_lines = logical_line.blank_lines;
+    let blank_before = logical_line.blank_before;
+    let text = logical_line.text.as_str();
+    // The physical line the logical line ends on.
+    let line_number = logical_line.rows().1;
+    let error = |code: &'static str, message: String| {
+        Some(logical_line.diagnostic(0, Code::new(code), message))
+    };
+
+    if previous_logical.is_empty() && blank_before < TOP_LEVEL_LINES {
+        // Don't expect blank lines before the first line
+        return None
     }
+    if previous_logical.starts_with('@') {
+        if blank_lines > 0 {
+            return error("E304", format!("blank lines found after function decorator ({})",
+                                         blank_lines))
+        }
+    } else if blank_lines > TOP_LEVEL_LINES ||
+        (indent_level > 0 && blank_lines == METHOD_LINES + 1) {
+        return error("E303", format!("too many blank lines ({})", blank_lines))
+    } else if starts_with_top_level(text) {
+        // allow a group of one-liners
+        if is_one_liner(text, indent_level, lines, line_number) && blank_before == 0 {
+            return None
+        }
+        if indent_level > 0 {
+            let docstring = DOCSTRING_REGEX.get_or_init(|| Regex::new(r#"^u?r?["']"#).unwrap());
+            if !(blank_before == METHOD_LINES || previous_indent_level < indent_level ||
+                 docstring.is_match(previous_logical)) {
+                let mut ancestor_level = indent_level;
+                let mut nested = false;
+                // Search backwards for a def ancestor or tree root (top level).
+                for line in lines[..line_number.saturating_sub(1)].iter().rev() {
+                    if !line.trim().is_empty() && expand_indent(line) < ancestor_level {
+                        ancestor_level = expand_indent(line);
+                        nested = starts_with_def(line.trim_start());
+                        if nested || ancestor_level == 0 {
+                            break
+                        }
+                    }
+                }
+                if nested {
+                    return error("E306", format!("expected {} blank line before a nested \
+                                                  definition, found 0", METHOD_LINES))
+                }
+                return error("E301", format!("expected {} blank line, found 0", METHOD_LINES))
+            }
+        } else if blank_before != TOP_LEVEL_LINES {
+            return error("E302", format!("expected {} blank lines, found {}",
+                                         TOP_LEVEL_LINES, blank_before))
+        }
+    } else if !text.is_empty() && indent_level == 0 && blank_before != TOP_LEVEL_LINES &&
+        (previous_unindented_logical.starts_with("def ") ||
+         previous_unindented_logical.starts_with("class ")) {
+        return error("E305", format!("expected {} blank lines after class or function \
+                                      definition, found {}", TOP_LEVEL_LINES, blank_before))
+    }
+    None
 }
 
-fn extraneous_whitespace(line: &str) -> Vec<Option<Error>> {
-    //    Avoid extraneous whitespace.
-    //
-    //    Avoid extraneous whitespace in these situations:
-    //    - Immediately inside parentheses, brackets or braces.
-    //    - Immediately before a comma, semicolon, or colon.
-    //
-    //    Okay: spam(ham[1], {eggs: 2})
-    //    E201: spam( ham[1], {eggs: 2})
-    //    E201: spam(ham[ 1], {eggs: 2})
-    //    E201: spam(ham[1], { eggs: 2})
-    //    E202: spam(ham[1], {eggs: 2} )
-    //    E202: spam(ham[1 ], {eggs: 2})
-    //    E202: spam(ham[1], {eggs: 2 })
-    //
-    //    E203: if x == 4: print x, y; x, y = y , x
-    //    E203: if x == 4: print x, y ; x, y = y, x
-    //    E203: if x == 4 : print x, y; x, y = y, x
-    let re = Regex::new(r"[\[\(\{] | [\]\}\),;:]").unwrap();
+const EXTRANEOUS_WHITESPACE: &str = r"Avoid extraneous whitespace.
+
+Avoid extraneous whitespace in these situations:
+- Immediately inside parentheses, brackets or braces.
+- Immediately before a comma, semicolon, or colon.
+
+Okay: spam(ham[1], {eggs: 2})
+E201: spam( ham[1], {eggs: 2})
+E201: spam(ham[ 1], {eggs: 2})
+E201: spam(ham[1], { eggs: 2})
+E202: spam(ham[1], {eggs: 2} )
+E202: spam(ham[1 ], {eggs: 2})
+E202: spam(ham[1], {eggs: 2 })
+
+E203: if x == 4: print x, y; x, y = y , x
+E203: if x == 4: print x, y ; x, y = y, x
+E203: if x == 4 : print x, y; x, y = y, x";
+
+fn extraneous_whitespace(logical_line: &LogicalLine) -> Vec<Option<Diagnostic>> {
+    let tokens = logical_line.code_tokens();
     let mut errors = Vec::new();
-    for match_ in re.find_iter(line) {
-        let text = match_.as_str();
-        let char = text.trim().to_string();
-        let found = match_.start();
-        let before_char = &line.chars().nth(found - 1).unwrap();
-
-        if text == char.clone() + " " {
-            let error = Error {
-                error_message: format!("E201 whitespace after {}", &char),
-                column_number: found + 1
-            };
+    for pair in tokens.windows(2) {
+        let (previous, token) = (&pair[0], &pair[1]);
+        if logical_line.whitespace_between(previous, token).is_empty() {
+            continue
+        }
+
+        if is_operator(previous.token, &["(", "[", "{"]) {
+            let error = logical_line.diagnostic(
+                previous.end, Code::new("E201"),
+                format!("whitespace after {}", previous.token.text));
             errors.push(Some(error));
-        } else if before_char != &',' {
-            let error_code = determine_extraneous_whitespace_error_code(text.trim().chars().nth(0).unwrap());
-            let error = Error {
-                error_message: format!("{} whitespace before {}", error_code,
-                                       &char),
-                column_number: found + 1
-            };
+        } else if is_operator(token.token, &[")", "]", "}", ",", ";", ":"]) &&
+            previous.token.text != "," {
+            let error_code = determine_extraneous_whitespace_error_code(&token.token.text);
+            let error = logical_line.diagnostic(
+                token.start - 1, Code::new(error_code),
+                format!("whitespace before {}", token.token.text));
             errors.push(Some(error));
         }
     }
     errors
 }
 
-fn determine_extraneous_whitespace_error_code(char: char) -> &'static str {
-    if char == '}' || char == ']' || char == ')' {
+fn determine_extraneous_whitespace_error_code(text: &str) -> &'static str {
+    if text == "}" || text == "]" || text == ")" {
         "E202"
     }
     else {
@@ -278,64 +594,72 @@ fn determine_extraneous_whitespace_error_code(char: char) -> &'static str {
     }
 }
 
-fn whitespace_around_keywords(line: &str) -> Vec<Option<Error>>{
+const WHITESPACE_AROUND_KEYWORDS: &str = r"Avoid extraneous whitespace around keywords.
+
+Okay: True and False
+E271: True and  False
+E272: True  and False
+E273: True and\tFalse
+E274: True\tand False";
+
+fn whitespace_around_keywords(logical_line: &LogicalLine) -> Vec<Option<Diagnostic>>{
     let keywords = get_keywords();
-    let joined_keywords = keywords.join("|");
-    let regex_string = format!(r"(\s*)\b(?:{})\b(\s*)", joined_keywords);
-    let re = Regex::new(regex_string.as_str()).unwrap();
+    let tokens = logical_line.code_tokens();
     let mut errors = Vec::new();
-    for match_ in re.find_iter(line) {
-        let start = match_.start();
-        let end = match_.end();
-
-        if line.chars().nth(start).unwrap() == '\t' {
-            let error = Error {
-                error_message: "E274 tab before keyword".to_string(),
-                column_number: start
-            };
-            errors.push(Some(error))
-        } else if line.chars().nth(start).unwrap() == ' ' &&
-            line.chars().nth(start + 1).unwrap() == ' ' {
-            let error = Error {
-                error_message: "E272 multiple spaces before keyword".to_string(),
-                column_number: start
-            };
-            errors.push(Some(error))
+    for (index, token) in tokens.iter().enumerate() {
+        if token.token.token_type != TokenType::Name ||
+            !keywords.contains(&token.token.text.as_str()) {
+            continue
         }
 
-        if line.chars().nth(end - 1).unwrap() == '\t' {
-            let error = Error {
-                error_message: "E273 tab after keyword".to_string(),
-                column_number: end
-            };
-            errors.push(Some(error))
-        } else if line.chars().nth(end - 1).unwrap() == ' ' &&
-            line.chars().nth(end - 2).unwrap() == ' ' {
-            let error = Error {
-                error_message: "E271 multiple spaces after keyword".to_string(),
-                column_number: end
-            };
-            errors.push(Some(error))
+        if index > 0 {
+            let previous = &tokens[index - 1];
+            let before = logical_line.whitespace_between(previous, token);
+            if before.contains('\t') {
+                let error = logical_line.diagnostic(previous.end, Code::new("E274"),
+                                                    "tab before keyword".to_string());
+                errors.push(Some(error))
+            } else if before.chars().count() > 1 {
+                let error = logical_line.diagnostic(
+                    previous.end, Code::new("E272"),
+                    "multiple spaces before keyword".to_string());
+                errors.push(Some(error))
+            }
+        }
+
+        if let Some(next) = tokens.get(index + 1) {
+            let after = logical_line.whitespace_between(token, next);
+            if after.contains('\t') {
+                let error = logical_line.diagnostic(token.end, Code::new("E273"),
+                                                    "tab after keyword".to_string());
+                errors.push(Some(error))
+            } else if after.chars().count() > 1 {
+                let error = logical_line.diagnostic(
+                    token.end, Code::new("E271"),
+                    "multiple spaces after keyword".to_string());
+                errors.push(Some(error))
+            }
         }
     }
     errors
 }
 
-fn missing_whitespace_after_import_keyword(line: &str) -> Option<Error> {
-//    Multiple imports in form from x import (a, b, c) should have space
-//    between import statement and parenthesised name list.
-//
-//    Okay: from foo import (bar, baz)
-//    E275: from foo import(bar, baz)
-//    E275: from importable.module import(bar, baz)
+const MISSING_WHITESPACE_AFTER_IMPORT_KEYWORD: &str = r"Multiple imports in form from x import (a, b, c) should have space
+between import statement and parenthesised name list.
+
+Okay: from foo import (bar, baz)
+E275: from foo import(bar, baz)
+E275: from importable.module import(bar, baz)";
+
+fn missing_whitespace_after_import_keyword(logical_line: &LogicalLine) -> Option<Diagnostic> {
+    let line = &logical_line.text;
     let indicator = " import(";
     if line.starts_with("from ") {
         let found = line.find(indicator);
-        if found.is_some() {
-            let error = Error {
-                error_message: "E275: missing whitespace after keyword import".to_string(),
-                column_number: found.unwrap() + indicator.len() - 1
-            };
+        if let Some(found) = found {
+            let error = logical_line.diagnostic(
+                char_offset(line, found) + indicator.len() - 1, Code::new("E275"),
+                "missing whitespace after keyword import".to_string());
             Some(error)
         }
         else {
@@ -347,217 +671,763 @@ fn missing_whitespace_after_import_keyword(line: &str) -> Option<Error> {
     }
 }
 
-fn missing_whitespace(line: &str) -> Vec<Option<Error>> {
-//    Each comma, semicolon or colon should be followed by whitespace.
-//
-//    Okay: [a, b]
-//    Okay: (3,)
-//    Okay: a[1:4]
-//    Okay: a[:4]
-//    Okay: a[1:]
-//    Okay: a[1:4:2]
-//    E231: ['a','b']
-//    E231: foo(bar,baz)
-//    E231: [{'a':'b'}]
+const MISSING_WHITESPACE: &str = r"Each comma, semicolon or colon should be followed by whitespace.
+
+Okay: [a, b]
+Okay: (3,)
+Okay: a[1:4]
+Okay: a[:4]
+Okay: a[1:]
+Okay: a[1:4:2]
+E231: ['a','b']
+E231: foo(bar,baz)
+E231: [{'a':'b'}]";
+
+fn missing_whitespace(logical_line: &LogicalLine) -> Vec<Option<Diagnostic>> {
+    let tokens = logical_line.code_tokens();
     let mut errors = Vec::new();
+    let mut brackets = Vec::new();
 
-    for (index, char) in line.chars().enumerate() {
-        let valid_char = char == ',' || char == ';' || char == ':';
-        if line.chars().nth(index + 1).is_none() {
+    for (index, token) in tokens.iter().enumerate() {
+        if is_operator(token.token, &["(", "[", "{"]) {
+            brackets.push(token.token.text.as_str());
+        } else if is_operator(token.token, &[")", "]", "}"]) {
+            brackets.pop();
+        }
+        if !is_operator(token.token, &[",", ";", ":"]) {
             continue
         }
-        if valid_char && (line.chars().nth(index + 1).unwrap() != ' ' ||
-            line.chars().nth(index + 1).unwrap() != '\t') {
-            let before: String = line.chars().take(index).collect();
-            if char == ':' && before.matches('[').count() > before.matches(']').count() &&
-                before.rfind('{') < before.rfind('['){
-                continue
-            }
-            if char == ',' && line.chars().nth(index + 1).unwrap() == ')' {
-                continue
-            }
-            if char == ',' && line.chars().nth(index + 1).unwrap() == ' ' {
-                continue
-            }
-            let error = Error {
-                error_message: format!("E231 missing whitespace after {}", char),
-                column_number: index,
-            };
-            errors.push(Some(error))
+        let next = match tokens.get(index + 1) {
+            Some(next) if next.start == token.end => next,
+            _ => continue,
+        };
+        if token.token.text == ":" && brackets.last() == Some(&"[") {
+            // Slice syntax, no space required
+            continue
+        }
+        if token.token.text == "," && is_operator(next.token, &[")", "]"]) {
+            continue
         }
+        let error = logical_line.diagnostic(
+            token.start, Code::new("E231"),
+            format!("missing whitespace after {}", token.token.text));
+        errors.push(Some(error))
     }
     errors
 }
 
 
-fn indentation(line: &str, previous_line: &str,
-                indent_level: usize, previous_indent_level: usize) -> Option<Error>{
-//    Use 4 spaces per indentation level.
-//
-//    For really old code that you don't want to mess up, you can continue to
-//    use 8-space tabs.
-//
-//    Okay: a = 1
-//    Okay: if a == 0:\n    a = 1
-//    E111:   a = 1
-//    E114:   # a = 1
-//
-//    Okay: for item in items:\n    pass
-//    E112: for item in items:\npass
-//    E115: for item in items:\n# Hi\n    pass
-//
-//    Okay: a = 1\nb = 2
-//    E113: a = 1\n    b = 2
-//    E116: a = 1\n    # b = 2
-    let comment = line.to_string().trim_left().starts_with("#");
-    let indent_expected = previous_line.to_string().ends_with(":");
-    if indent_level % 4 != 0 && comment == false {
-        let error = Error {
-            error_message: "E111: indentation is not a multiple of four".to_string(),
-            column_number: 0
-        };
+const INDENTATION: &str = r"Use 4 spaces per indentation level.
+
+For really old code that you don't want to mess up, you can continue to
+use 8-space tabs.
+
+Okay: a = 1
+Okay: if a == 0:\n    a = 1
+E111:   a = 1
+E114:   # a = 1
+
+Okay: for item in items:\n    pass
+E112: for item in items:\npass
+E115: for item in items:\n# Hi\n    pass
+
+Okay: a = 1\nb = 2
+E113: a = 1\n    b = 2
+E116: a = 1\n    # b = 2";
+
+fn indentation(logical_line: &LogicalLine, previous_logical: &str,
+               indent_level: usize, previous_indent_level: usize) -> Option<Diagnostic>{
+    let comment = logical_line.text.is_empty();
+    let indent_expected = previous_logical.ends_with(":");
+    if !indent_level.is_multiple_of(4) && !comment {
+        let error = logical_line.diagnostic(
+            0, Code::new("E111"),
+            "indentation is not a multiple of four".to_string());
         return Some(error)
     }
-    else if indent_level % 4 != 0 && comment == true {
-        let error = Error {
-            error_message: "E114: indentation is not a multiple of four (comment)".to_string(),
-            column_number: 0
-        };
+    else if !indent_level.is_multiple_of(4) && comment {
+        let error = logical_line.diagnostic(
+            0, Code::new("E114"),
+            "indentation is not a multiple of four (comment)".to_string());
         return Some(error)
     }
 
     if indent_expected && indent_level <= previous_indent_level
-        && comment == false {
-        let error = Error {
-            error_message: "E112: expected an indented block".to_string(),
-            column_number: 0,
-        };
+        && !comment {
+        let error = logical_line.diagnostic(0, Code::new("E112"),
+                                            "expected an indented block".to_string());
         return Some(error)
     } else if !indent_expected && indent_level > previous_indent_level
-        && comment == false {
-        let error = Error {
-            error_message: "E113: unexpected indentation".to_string(),
-            column_number: 0,
-        };
+        && !comment {
+        let error = logical_line.diagnostic(0, Code::new("E113"),
+                                            "unexpected indentation".to_string());
         return Some(error)
     } else if indent_expected && indent_level <= previous_indent_level
-        && comment == true {
-        let error = Error {
-            error_message: "E115: expected an indented block (comment)".to_string(),
-            column_number: 0,
-        };
+        && comment {
+        let error = logical_line.diagnostic(
+            0, Code::new("E115"),
+            "expected an indented block (comment)".to_string());
         return Some(error)
     } else if !indent_expected && indent_level > previous_indent_level
-        && comment == true {
-        let error = Error {
-            error_message: "E116: unexpected indentation (comment)".to_string(),
-            column_number: 0,
-        };
+        && comment {
+        let error = logical_line.diagnostic(0, Code::new("E116"),
+                                            "unexpected indentation (comment)".to_string());
         return Some(error)
     }
-    return None
+    None
 }
 
-fn whitespace_around_operator(line: &str) -> Vec<Option<Error>>{
-//    Avoid extraneous whitespace around an operator.
-//
-//    Okay: a = 12 + 3
-//    E221: a = 4  + 5
-//    E222: a = 4 +  5
-//    E223: a = 4\t+ 5
-//    E224: a = 4 +\t5
-    let mut errors = Vec::new();
-    let re = Regex::new(r"(\s*)(?:[-+*/|!<=>%&^]+)(\s*)").unwrap();
-    for match_ in re.find_iter(line) {
+const CONTINUED_INDENTATION: &str = r"Continuation lines indentation.
+
+Continuation lines should align wrapped elements either vertically
+using Python's implicit line joining inside parentheses, brackets
+and braces, or using a hanging indent.
+
+When using a hanging indent these considerations should be applied:
+- there should be no arguments on the first line, and
+- further indentation should be used to clearly distinguish itself
+  as a continuation line.
+
+Okay: a = (\n)
+E123: a = (\n    )
+
+Okay: a = (\n    42)
+E121: a = (\n   42)
+E122: a = (\n42)
+E123: a = (\n    42\n    )
+E124: a = (24,\n     42\n)
+E125: if (\n    b):\n    pass
+E126: a = (\n        42)
+E127: a = (24,\n      42)
+E128: a = (24,\n    42)
+E129: if (a or\n    b):\n    pass
+E131: a = (\n    42\n 24)";
+
+// What lines up with a column in continued_indentation: a visual indent, a
+// string or comment, or a token with this text.
+#[derive(PartialEq, Debug, Clone)]
+enum IndentChance {
+    Visual,
+    Str,
+    Text(String),
+}
 
-        let start = match_.start();
-        let end = match_.end();
+fn continued_indentation(logical_line: &LogicalLine, indent_char: char,
+                         hang_closing: bool) -> Vec<Diagnostic> {
+    let tokens = &logical_line.tokens;
+    let mut errors = Vec::new();
+    let (first, last) = match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return errors,
+    };
+    let first_row = first.start.0;
+    let nrows = 1 + last.start.0 - first_row;
+    if nrows == 1 {
+        return errors
+    }
+    let indent_size = 4;
+    let indent_level = expand_indent(&first.line) as i64;
+
+    // indent_next tells us whether the next block is indented; assuming
+    // that it is indented by 4 spaces, then we should not allow 4-space
+    // indents on the final continuation line; in turn, some other
+    // indents are allowed to have an extra 4 spaces.
+    let indent_next = logical_line.text.ends_with(':');
+
+    let mut row = 0;
+    let mut depth = 0;
+    let valid_hangs: &[i64] = if indent_char != '\t' { &[4] } else { &[4, 8] };
+    // remember how many brackets were opened on each row
+    let mut parens = vec![0; nrows];
+    // relative indents of physical lines
+    let mut rel_indent: Vec<i64> = vec![0; nrows];
+    // for each depth, collect a list of opening rows
+    let mut open_rows: Vec<Vec<usize>> = vec![vec![0]];
+    // for each depth, memorize the hanging indentation
+    let mut hangs: Vec<Option<i64>> = vec![None];
+    // visual indents
+    let mut indent_chances: BTreeMap<usize, IndentChance> = BTreeMap::new();
+    let mut last_indent = first.start;
+    let mut visual_indent: Option<IndentChance> = None;
+    let mut last_token_multiline = false;
+    // for each depth, memorize the visual indent column
+    let mut indent = vec![last_indent.1];
+
+    for token in tokens {
+        let (token_type, text, start, end) = (token.token_type, token.text.as_str(),
+                                              token.start, token.end);
+        let mut newline = row < start.0 - first_row;
+        if newline {
+            row = start.0 - first_row;
+            newline = !last_token_multiline && token_type != TokenType::NL &&
+                token_type != TokenType::Newline;
+        }
 
-        if line.chars().nth(start).unwrap() == '\t' {
-            let error = Error {
-                error_message: "E223 tab before operator".to_string(),
-                column_number: start
-            };
-            errors.push(Some(error))
-        } else if line.chars().nth(start).unwrap() == ' ' &&
-            line.chars().nth(start + 1).unwrap() == ' '{
-            let error = Error {
-                error_message: "E221 multiple spaces before operator".to_string(),
-                column_number: start
+        if newline {
+            // this is the beginning of a continuation line.
+            last_indent = start;
+
+            // record the initial indent.
+            rel_indent[row] = expand_indent(&token.line) as i64 - indent_level;
+
+            // identify closing bracket
+            let close_bracket = token_type == TokenType::Op &&
+                (text == "]" || text == "}" || text == ")");
+
+            // is the indent relative to an opening bracket line?
+            let mut hang = 0;
+            let mut hanging_indent = false;
+            for &open_row in open_rows[depth].iter().rev() {
+                hang = rel_indent[row] - rel_indent[open_row];
+                hanging_indent = valid_hangs.contains(&hang);
+                if hanging_indent {
+                    break
+                }
+            }
+            if let Some(depth_hang) = hangs[depth].filter(|&depth_hang| depth_hang != 0) {
+                hanging_indent = hang == depth_hang;
+            }
+            // is there any chance of visual indent?
+            visual_indent = if !close_bracket && hang > 0 {
+                indent_chances.get(&start.1).cloned()
+            } else {
+                None
             };
-            errors.push(Some(error))
+
+            let error = |code: &'static str, message: &str| Diagnostic::new(
+                Code::new(code), start.0, start.1, message.to_string());
+            if close_bracket && indent[depth] != 0 {
+                // closing bracket for visual indent
+                if start.1 != indent[depth] {
+                    errors.push(error("E124", "closing bracket does not match visual indentation"));
+                }
+            } else if close_bracket && hang == 0 {
+                // closing bracket matches indentation of opening line's
+                // bracket
+                if hang_closing {
+                    errors.push(error("E133", "closing bracket is missing indentation"));
+                }
+            } else if indent[depth] != 0 && start.1 < indent[depth] {
+                if visual_indent != Some(IndentChance::Visual) {
+                    // visual indent is broken
+                    errors.push(error("E128", "continuation line under-indented for visual \
+                                               indent"));
+                }
+            } else if hanging_indent || (indent_next && rel_indent[row] == 2 * indent_size) {
+                // hanging indent is verified
+                if close_bracket && !hang_closing {
+                    errors.push(error("E123", "closing bracket does not match indentation of \
+                                               opening line's bracket"));
+                }
+                hangs[depth] = Some(hang);
+            } else if visual_indent == Some(IndentChance::Visual) {
+                // visual indent is verified
+                indent[depth] = start.1;
+            } else if visual_indent == Some(IndentChance::Str) ||
+                visual_indent == Some(IndentChance::Text(text.to_string())) {
+                // ignore token lined up with matching one from a previous line
+            } else {
+                // indent is broken
+                let (code, message) = if hang <= 0 {
+                    ("E122", "missing indentation or outdented")
+                } else if indent[depth] != 0 {
+                    ("E127", "over-indented for visual indent")
+                } else if !close_bracket && hangs[depth].is_some_and(|hang| hang != 0) {
+                    ("E131", "unaligned for hanging indent")
+                } else {
+                    hangs[depth] = Some(hang);
+                    if hang > indent_size {
+                        ("E126", "over-indented for hanging indent")
+                    } else {
+                        ("E121", "under-indented for hanging indent")
+                    }
+                };
+                errors.push(error(code, &format!("continuation line {}", message)));
+            }
         }
 
-        if line.chars().nth(end - 1).unwrap() == '\t' {
-            let error = Error {
-                error_message: "E224 tab after operator".to_string(),
-                column_number: end - 1
-            };
+        // look for visual indenting
+        if parens[row] != 0 && token_type != TokenType::NL && token_type != TokenType::Comment &&
+            indent[depth] == 0 {
+            indent[depth] = start.1;
+            indent_chances.insert(start.1, IndentChance::Visual);
+        }
+        // deal with implicit string concatenation
+        else if token_type == TokenType::String || token_type == TokenType::Comment ||
+            ["u", "ur", "b", "br"].contains(&text) {
+            indent_chances.insert(start.1, IndentChance::Str);
+        }
+        // visual indent after assert/raise/with, and the special case for
+        // the "if" statement because len("if (") == len("elif")
+        else if row == 0 && depth == 0 &&
+            (["assert", "raise", "with"].contains(&text) ||
+             (text == "if" && indent_chances.is_empty())) {
+            indent_chances.insert(end.1 + 1, IndentChance::Visual);
+        }
+        else if text == ":" {
+            let rest: String = token.line.chars().skip(end.1).collect();
+            if !rest.is_empty() && rest.chars().all(char::is_whitespace) {
+                open_rows[depth].push(row);
+            }
+        }
+
+        // keep track of bracket depth
+        if token_type == TokenType::Op {
+            if text == "(" || text == "[" || text == "{" {
+                depth += 1;
+                indent.push(0);
+                hangs.push(None);
+                if open_rows.len() == depth {
+                    open_rows.push(Vec::new());
+                }
+                open_rows[depth].push(row);
+                parens[row] += 1;
+            } else if (text == ")" || text == "]" || text == "}") && depth > 0 {
+                // parent indents should not be more than this one
+                let prev_indent = match indent.pop() {
+                    Some(0) | None => last_indent.1,
+                    Some(prev_indent) => prev_indent,
+                };
+                hangs.pop();
+                for indent in indent.iter_mut().take(depth) {
+                    if *indent > prev_indent {
+                        *indent = 0;
+                    }
+                }
+                indent_chances.retain(|&column, _| column < prev_indent);
+                open_rows.truncate(depth + 1);
+                depth -= 1;
+                if depth != 0 {
+                    indent_chances.insert(indent[depth], IndentChance::Visual);
+                }
+                for index in (0..=row).rev() {
+                    if parens[index] != 0 {
+                        parens[index] -= 1;
+                        break
+                    }
+                }
+            }
+            // allow lining up tokens
+            indent_chances.entry(start.1).or_insert_with(|| IndentChance::Text(text.to_string()));
+        }
+
+        last_token_multiline = start.0 != end.0;
+        if last_token_multiline && end.0 - first_row < nrows {
+            rel_indent[end.0 - first_row] = rel_indent[row];
+        }
+    }
+
+    if indent_next && expand_indent(&last.line) as i64 == indent_level + indent_size {
+        let (code, message) = if visual_indent.is_some() {
+            ("E129", "visually indented line")
+        } else {
+            ("E125", "continuation line")
+        };
+        errors.push(Diagnostic::new(Code::new(code), last.start.0,
+                                    indent[0] + indent_size as usize,
+                                    format!("{} with same indent as next logical line",
+                                            message)));
+    }
+    errors
+}
+
+const WHITESPACE_AROUND_OPERATOR: &str = r"Avoid extraneous whitespace around an operator.
+
+Okay: a = 12 + 3
+E221: a = 4  + 5
+E222: a = 4 +  5
+E223: a = 4\t+ 5
+E224: a = 4 +\t5";
+
+fn whitespace_around_operator(logical_line: &LogicalLine) -> Vec<Option<Diagnostic>>{
+    let tokens = logical_line.code_tokens();
+    let mut errors = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        let operator = token.token.token_type == TokenType::Op &&
+            (token.token.text == ":=" ||
+             token.token.text.chars().all(|c| "-+*/|!<=>%&^".contains(c)));
+        if !operator || index == 0 || tokens[index - 1].token.text == "," {
+            continue
+        }
+
+        let previous = &tokens[index - 1];
+        let before = logical_line.whitespace_between(previous, token);
+        if before.contains('\t') {
+            let error = logical_line.diagnostic(previous.end, Code::new("E223"),
+                                                "tab before operator".to_string());
             errors.push(Some(error))
-        } else if line.chars().nth(end - 1).unwrap() == ' ' &&
-            line.chars().nth(end - 2).unwrap() == ' '{
-            let error = Error {
-                error_message: "E222 multiple spaces after operator".to_string(),
-                column_number: end - 2
-            };
+        } else if before.chars().count() > 1 {
+            let error = logical_line.diagnostic(
+                previous.end, Code::new("E221"),
+                "multiple spaces before operator".to_string());
             errors.push(Some(error))
         }
+
+        if let Some(next) = tokens.get(index + 1) {
+            let after = logical_line.whitespace_between(token, next);
+            if after.contains('\t') {
+                let error = logical_line.diagnostic(token.end, Code::new("E224"),
+                                                    "tab after operator".to_string());
+                errors.push(Some(error))
+            } else if after.chars().count() > 1 {
+                let error = logical_line.diagnostic(
+                    token.end, Code::new("E222"),
+                    "multiple spaces after operator".to_string());
+                errors.push(Some(error))
+            }
+        }
     }
-     errors
+    errors
 }
 
-fn whitespace_around_comma(line: &str) -> Vec<Option<Error>> {
-//    Avoid extraneous whitespace after a comma or a colon.
-//
-//    Note: these checks are disabled by default
-//
-//    Okay: a = (1, 2)
-//    E241: a = (1,  2)
-//    E242: a = (1,\t2)
+const MISSING_WHITESPACE_AROUND_OPERATOR: &str = r"Surround operators with a single space on either side.
+
+- Always surround these binary operators with a single space on
+  either side: assignment (=), augmented assignment (+=, -= etc.),
+  comparisons (==, <, >, !=, <=, >=, in, not in, is, is not),
+  Booleans (and, or, not).
+
+- If operators with different priorities are used, consider adding
+  whitespace around the operators with the lowest priorities.
+
+Okay: i = i + 1
+Okay: submitted += 1
+Okay: x = x * 2 - 1
+Okay: hypot2 = x * x + y * y
+Okay: c = (a + b) * (a - b)
+Okay: foo(bar, key='word', *args, **kwargs)
+Okay: alpha[:-i]
+
+E225: i=i+1
+E225: submitted +=1
+E225: x = x /2 - 1
+E225: z = x **y
+E226: c = (a+b) * (a-b)
+E226: hypot2 = x*x + y*y
+E227: c = a|b
+E228: msg = fmt%(errno, errmsg)";
+
+const UNARY_OPERATORS: &[&str] = &[">>", "**", "*", "+", "-"];
+const ARITHMETIC_OPERATORS: &[&str] = &["**", "*", "/", "//", "+", "-", "@"];
+const WS_OPTIONAL_OPERATORS: &[&str] = &["**", "*", "/", "//", "+", "-", "@",
+                                         "^", "&", "|", "<<", ">>", "%"];
+const WS_NEEDED_OPERATORS: &[&str] = &["**=", "*=", "/=", "//=", "+=", "-=", "!=", "<", ">",
+                                       "%=", "^=", "&=", "|=", "==", "<=", ">=", "<<=", ">>=",
+                                       "=", "and", "in", "is", "or", "->", ":="];
+
+// Whether an operator still needs whitespace after it in
+// missing_whitespace_around_operator.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum NeedSpace {
+    No,
+    Yes,
+    // The space is optional, but it must match the one before the
+    // operator: the end of the previous token and whether there was one.
+    Optional((usize, usize), bool),
+}
+
+fn missing_whitespace_around_operator(logical_line: &LogicalLine) -> Vec<Diagnostic> {
+    let keywords = get_keywords();
+    let mut errors = Vec::new();
+    let error = |code: &'static str, (row, column): (usize, usize), message: &str| {
+        Diagnostic::new(Code::new(code), row, column, message.to_string())
+    };
+    let mut parens = 0;
+    let mut need_space = NeedSpace::No;
+    let mut prev_type = TokenType::Op;
+    let mut prev_text = "";
+    let mut prev_end: Option<(usize, usize)> = None;
+
+    for token in &logical_line.tokens {
+        let (token_type, text, start, end) = (token.token_type, token.text.as_str(),
+                                              token.start, token.end);
+        match token_type {
+            TokenType::NL | TokenType::Newline | TokenType::Indent | TokenType::Dedent |
+            TokenType::EndMarker | TokenType::Comment | TokenType::ErrorToken => continue,
+            _ => (),
+        }
+        if text == "(" || text == "lambda" {
+            parens += 1;
+        } else if text == ")" {
+            parens -= 1;
+        }
+        if need_space != NeedSpace::No {
+            if Some(start) != prev_end {
+                // Found a (probably) needed space
+                if let NeedSpace::Optional(operator_start, false) = need_space {
+                    errors.push(error("E225", operator_start,
+                                      "missing whitespace around operator"));
+                }
+                need_space = NeedSpace::No;
+            } else if (prev_text == "/" && (text == "," || text == ")" || text == ":")) ||
+                (prev_text == ")" && text == ":") {
+                // Tolerate the "/" operator in function definition
+                // For more info see PEP570
+            } else {
+                match need_space {
+                    NeedSpace::Yes | NeedSpace::Optional(_, true) => {
+                        // A needed trailing space was not found
+                        errors.push(error("E225", prev_end.unwrap_or(start),
+                                          "missing whitespace around operator"));
+                    }
+                    NeedSpace::Optional(operator_start, false) if prev_text != "**" => {
+                        let (code, operator_type) = if prev_text == "%" {
+                            ("E228", "modulo")
+                        } else if !ARITHMETIC_OPERATORS.contains(&prev_text) {
+                            ("E227", "bitwise or shift")
+                        } else {
+                            ("E226", "arithmetic")
+                        };
+                        errors.push(error(code, operator_start,
+                                          &format!("missing whitespace around {} operator",
+                                                   operator_type)));
+                    }
+                    _ => (),
+                }
+                need_space = NeedSpace::No;
+            }
+        } else if (token_type == TokenType::Op || token_type == TokenType::Name) &&
+            prev_end.is_some() {
+            let mut optional = false;
+            if text == "=" && parens > 0 {
+                // Allow keyword args or defaults: foo(bar=None).
+            } else if WS_NEEDED_OPERATORS.contains(&text) {
+                need_space = NeedSpace::Yes;
+            } else if UNARY_OPERATORS.contains(&text) {
+                // Check if the operator is used as a binary operator
+                // Allow unary operators: -123, -x, +1.
+                // Allow argument unpacking: foo(*args, **kwargs).
+                optional = if prev_type == TokenType::Op {
+                    prev_text == "}" || prev_text == "]" || prev_text == ")"
+                } else {
+                    !keywords.contains(&prev_text)
+                };
+            } else if WS_OPTIONAL_OPERATORS.contains(&text) {
+                optional = true;
+            }
+
+            let prev_end = prev_end.unwrap_or(start);
+            if optional {
+                // Surrounding space is optional, but ensure that
+                // trailing space matches opening space
+                need_space = NeedSpace::Optional(prev_end, start != prev_end);
+            } else if need_space == NeedSpace::Yes && start == prev_end {
+                // A needed opening space was not found
+                errors.push(error("E225", prev_end, "missing whitespace around operator"));
+                need_space = NeedSpace::No;
+            }
+        }
+        prev_type = token_type;
+        prev_text = text;
+        prev_end = Some(end);
+    }
+    errors
+}
+
+const WHITESPACE_AROUND_DEFAULT_EQUALS: &str = r"Don't use spaces around the '=' sign in function arguments.
+
+Don't use spaces around the '=' sign when used to indicate a
+keyword argument or a default parameter value, except when
+using a type annotation.
+
+Okay: def complex(real, imag=0.0):
+Okay: return magic(r=real, i=imag)
+Okay: boolean(a == b)
+Okay: boolean(a != b)
+Okay: boolean(a <= b)
+Okay: boolean(a >= b)
+Okay: def foo(arg: int = 42):
+Okay: async def foo(arg: int = 42):
+
+E251: def complex(real, imag = 0.0):
+E251: return magic(r = real, i = imag)
+E252: def complex(real, image: float=0.0):";
+
+fn whitespace_around_default_equals(logical_line: &LogicalLine) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    let mut parens = 0;
+    let mut no_space = false;
+    let mut require_space = false;
+    let mut prev_end: Option<(usize, usize)> = None;
+    let mut annotated_func_arg = false;
+    let in_def = starts_with_def(&logical_line.text);
+
+    let message = "unexpected spaces around keyword / parameter equals";
+    let missing_message = "missing whitespace around parameter equals";
+    let mut error = |code: &'static str, position: Option<(usize, usize)>, message: &str| {
+        if let Some((row, column)) = position {
+            errors.push(Diagnostic::new(Code::new(code), row, column, message.to_string()));
+        }
+    };
+
+    for token in &logical_line.tokens {
+        let (text, start) = (token.text.as_str(), Some(token.start));
+        if token.token_type == TokenType::NL {
+            continue
+        }
+        if no_space {
+            no_space = false;
+            if start != prev_end {
+                error("E251", prev_end, message);
+            }
+        }
+        if require_space {
+            require_space = false;
+            if start == prev_end {
+                error("E252", prev_end, missing_message);
+            }
+        }
+        if token.token_type == TokenType::Op {
+            if text == "(" || text == "[" {
+                parens += 1;
+            } else if text == ")" || text == "]" {
+                parens -= 1;
+            } else if in_def && text == ":" && parens == 1 {
+                annotated_func_arg = true;
+            } else if parens == 1 && text == "," {
+                annotated_func_arg = false;
+            } else if parens > 0 && text == "=" {
+                if annotated_func_arg && parens == 1 {
+                    require_space = true;
+                    if start == prev_end {
+                        error("E252", prev_end, missing_message);
+                    }
+                } else {
+                    no_space = true;
+                    if start != prev_end {
+                        error("E251", prev_end, message);
+                    }
+                }
+            }
+            if parens == 0 {
+                // The return annotation after "->" is not a parameter.
+                annotated_func_arg = false;
+            }
+        }
+        prev_end = Some(token.end);
+    }
+    errors
+}
+
+const WHITESPACE_AROUND_COMMA: &str = r"Avoid extraneous whitespace after a comma or a colon.
+
+Note: these checks are disabled by default
+
+Okay: a = (1, 2)
+E241: a = (1,  2)
+E242: a = (1,\t2)";
+
+fn whitespace_around_comma(logical_line: &LogicalLine) -> Vec<Option<Diagnostic>> {
     let mut errors = Vec::new();
     let re = Regex::new(r"[,;:]\s*(?:  |\t)").unwrap();
 
-    for match_ in re.find_iter(line) {
-        let start = match_.start();
+    for match_ in re.find_iter(&logical_line.text) {
+        let start = char_offset(&logical_line.text, match_.start());
 
         if match_.as_str().contains('\t') {
-            let error = Error {
-                error_message: format!("E242 tab after {}", match_.as_str().trim()),
-                column_number: start + 1
-            };
+            let error = logical_line.diagnostic(
+                start + 1, Code::new("E242"),
+                format!("tab after {}", match_.as_str().trim()));
             errors.push(Some(error))
         }
         else {
-            let error = Error {
-                error_message: format!("E241 multiple spaces after {}", match_.as_str().trim()),
-                column_number: start + 1
-            };
+            let error = logical_line.diagnostic(
+                start + 1, Code::new("E241"),
+                format!("multiple spaces after {}", match_.as_str().trim()));
             errors.push(Some(error))
         }
     }
     errors
 }
 
-fn imports_on_separate_lines(line: &str) -> Option<Error>{
-//    Place imports on separate lines.
-//
-//    Okay: import os\nimport sys
-//    E401: import sys, os
-//
-//    Okay: from subprocess import Popen, PIPE
-//    Okay: from myclas import MyClass
-//    Okay: from foo.bar.yourclass import YourClass
-//    Okay: import myclass
-//    Okay: import foo.bar.yourclass
-    let found = line.find(",");
-    if line.starts_with("import ") && found.is_some() {
-        let comma_position = found.unwrap();
+const WHITESPACE_BEFORE_COMMENT: &str = r"Separate inline comments by at least two spaces.
+
+An inline comment is a comment on the same line as a statement.
+Inline comments should be separated by at least two spaces from the
+statement. They should start with a # and a single space.
+
+Each line of a block comment starts with a # and one or multiple
+spaces as there can be indented text inside the comment.
+
+Okay: x = x + 1  # Increment x
+Okay: x = x + 1    # Increment x
+Okay: # Block comments:
+Okay: #  - Block comment list
+Okay: # \xa0- Block comment list
+E261: x = x + 1 # Increment x
+E262: x = x + 1  #Increment x
+E262: x = x + 1  #  Increment x
+E262: x = x + 1  # \xa0Increment x
+E265: #Block comment
+E266: ### Block comment";
+
+fn whitespace_before_comment(logical_line: &LogicalLine) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    let error = |code: &'static str, (row, column): (usize, usize), message: &str| {
+        Diagnostic::new(Code::new(code), row, column, message.to_string())
+    };
+    let mut prev_end = (0, 0);
+    for token in &logical_line.tokens {
+        let start = token.start;
+        if token.token_type != TokenType::Comment {
+            if token.token_type != TokenType::NL {
+                prev_end = token.end;
+            }
+            continue
+        }
+        let before: String = token.line.chars().take(start.1).collect();
+        let inline_comment = !before.trim().is_empty();
+        if inline_comment && prev_end.0 == start.0 && start.1 < prev_end.1 + 2 {
+            errors.push(error("E261", prev_end, "at least two spaces before inline comment"));
+        }
+        let (symbol, comment) = match token.text.find(' ') {
+            Some(index) => (&token.text[..index], &token.text[index + 1..]),
+            None => (token.text.as_str(), ""),
+        };
+        // The character after the leading "#" characters, or "#" if there
+        // are only those.  Comments like "#:" are left alone.
+        let bad_prefix = match symbol {
+            "" | "#" | ":" | "#:" => None,
+            symbol => Some(symbol.trim_start_matches('#').chars().next().unwrap_or('#')),
+        };
+        if inline_comment {
+            if bad_prefix.is_some() || comment.starts_with([' ', '\t', '\u{a0}']) {
+                errors.push(error("E262", start, "inline comment should start with '# '"));
+            }
+        } else if let Some(bad_prefix) = bad_prefix {
+            // A shebang is only allowed on the first line.
+            if bad_prefix == '!' && start.0 == 1 {
+                continue
+            }
+            if bad_prefix != '#' {
+                errors.push(error("E265", start, "block comment should start with '# '"));
+            } else if !comment.is_empty() {
+                errors.push(error("E266", start, "too many leading '#' for block comment"));
+            }
+        }
+    }
+    errors
+}
+
+const IMPORTS_ON_SEPARATE_LINES: &str = r"Place imports on separate lines.
+
+Okay: import os\nimport sys
+E401: import sys, os
+
+Okay: from subprocess import Popen, PIPE
+Okay: from myclas import MyClass
+Okay: from foo.bar.yourclass import YourClass
+Okay: import myclass
+Okay: import foo.bar.yourclass";
+
+fn imports_on_separate_lines(logical_line: &LogicalLine) -> Option<Diagnostic>{
+    let line = &logical_line.text;
+    let found = line.find(",").map(|found| char_offset(line, found));
+    if let (true, Some(comma_position)) = (line.starts_with("import "), found) {
         let sub_string: String = line.chars().skip(comma_position).collect();
         if !sub_string.contains(";") {
-            let error = Error {
-                error_message: "E401 multiple imports on one line".to_string(),
-                column_number: comma_position
-            };
+            let error = logical_line.diagnostic(comma_position, Code::new("E401"),
+                                                "multiple imports on one line".to_string());
             Some(error)
         }
         else {
@@ -570,19 +1440,121 @@ fn imports_on_separate_lines(line: &str) -> Option<Error>{
 
 }
 
+const MAXIMUM_DOC_LENGTH: &str = r"Limit all doc lines to a maximum of 72 characters.
+
+For flowing long blocks of text (docstrings or comments), limiting
+the length to 72 characters is recommended.
+
+Reports warning W505";
+
+fn maximum_doc_length(logical_line: &LogicalLine,
+                      max_doc_length: Option<usize>) -> Vec<Diagnostic> {
+    let max_doc_length = match max_doc_length {
+        Some(max_doc_length) => max_doc_length,
+        None => return Vec::new(),
+    };
+    let skip_tokens = [TokenType::NL, TokenType::Newline, TokenType::Indent,
+                       TokenType::Dedent, TokenType::EndMarker];
+    // Strings are only doc lines when the logical line holds nothing else.
+    let has_code = logical_line.tokens.iter().any(|token| {
+        !skip_tokens.contains(&token.token_type) &&
+            token.token_type != TokenType::Comment &&
+            token.token_type != TokenType::ErrorToken &&
+            token.token_type != TokenType::String
+    });
+
+    let mut errors = Vec::new();
+    let mut previous: Option<TokenType> = None;
+    for token in &logical_line.tokens {
+        let token_type = token.token_type;
+        if token_type == TokenType::String && has_code {
+            continue
+        }
+        // Only check comment-only lines
+        let starts_line = previous.is_none_or(|previous| skip_tokens.contains(&previous));
+        if (token_type == TokenType::String || token_type == TokenType::Comment) &&
+            starts_line {
+            let lines: Vec<&str> = token.line.lines().collect();
+            for (line_offset, physical_line) in lines.iter().enumerate() {
+                let line_number = token.start.0 + line_offset;
+                if line_number == 1 && token.line.starts_with("#!") {
+                    return errors
+                }
+                let length = physical_line.chars().count();
+                let chunks: Vec<&str> = physical_line.split_whitespace().collect();
+                let last_chunk = chunks.last().map_or(0, |chunk| chunk.chars().count());
+                // Long URLs and other single words can not be wrapped.
+                if token_type == TokenType::Comment && chunks.len() == 2 &&
+                    length - last_chunk < 72 {
+                    continue
+                }
+                if chunks.len() == 1 && line_offset + 1 < lines.len() &&
+                    length - last_chunk < 72 {
+                    continue
+                }
+                if length > max_doc_length {
+                    let message = format!("doc line too long ({} > {} characters)",
+                                          length, max_doc_length);
+                    let error = Diagnostic::new(Code::new("W505"), line_number,
+                                                max_doc_length, message)
+                        .with_end_column(length);
+                    errors.push(error);
+                }
+            }
+        }
+        previous = Some(token_type);
+    }
+    errors
+}
+
 #[cfg(test)]
 mod test_checks {
     use super::*;
 
+    // Check the "Okay: ..." and "E123: ..." examples of a doc, where "\\n",
+    // "\\t" and "\\xa0" stand for a newline, a tab and a no-break space,
+    // against the selected codes.
+    fn check_examples(doc: &str, options: &Options) {
+        for line in doc.lines() {
+            let (label, source) = match line.find(": ") {
+                Some(index) => (&line[..index], &line[index + 2..]),
+                None => continue,
+            };
+            let is_code = label.len() == 4 && label[1..].chars().all(|char| char.is_ascii_digit());
+            if label != "Okay" && !is_code {
+                continue
+            }
+            let source = format!("{}\n", source.replace("\\n", "\n").replace("\\t", "\t")
+                                 .replace("\\xa0", "\u{a0}"));
+            let codes: Vec<&str> = check_source(&source, options).iter()
+                .map(|diagnostic| diagnostic.code.as_str())
+                .collect();
+            if label == "Okay" {
+                assert_eq!(codes, Vec::<&str>::new(), "{}", line);
+            } else {
+                assert!(codes.contains(&label), "{}: {:?}", line, codes);
+            }
+        }
+    }
+
+    // The row, column and code of each diagnostic for source.
+    fn check_codes(source: &str, options: &Options) -> Vec<(usize, usize, &'static str)> {
+        check_source(source, options).iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.column, diagnostic.code.as_str()))
+            .collect()
+    }
+
+    fn logical_line(source: &str) -> LogicalLine {
+        build_logical_lines(&tokenize(source).unwrap()).remove(0)
+    }
+
     #[test]
     fn tabs_or_spaces_tabs_test() {
         let indent_char = ' ';
         let line = "\tHello world";
-        let error = tabs_or_spaces(line, indent_char).unwrap();
-        let expected_error = Error {
-            column_number: 0,
-            error_message: "E101 indentation contains mixed spaces and tabs".to_string()
-        };
+        let error = tabs_or_spaces(line, 1, indent_char).unwrap();
+        let expected_error = Diagnostic::new(Code::new("E101"), 1, 0,
+            "indentation contains mixed spaces and tabs".to_string());
         assert_eq!(error, expected_error);
     }
 
@@ -590,11 +1562,9 @@ mod test_checks {
     fn tabs_or_spaces_spaces_test() {
         let indent_char = '\t';
         let line = " Hello world";
-        let error = tabs_or_spaces(line, indent_char).unwrap();
-        let expected_error = Error {
-            column_number: 0,
-            error_message: "E101 indentation contains mixed spaces and tabs".to_string()
-        };
+        let error = tabs_or_spaces(line, 1, indent_char).unwrap();
+        let expected_error = Diagnostic::new(Code::new("E101"), 1, 0,
+            "indentation contains mixed spaces and tabs".to_string());
         assert_eq!(error, expected_error);
     }
 
@@ -602,54 +1572,50 @@ mod test_checks {
     fn tabs_or_spaces_base_test() {
         let indent_char = ' ';
         let line = " Hello world";
-        let error = tabs_or_spaces(line, indent_char);
+        let error = tabs_or_spaces(line, 1, indent_char);
         assert_eq!(None, error);
     }
 
     #[test]
     fn tabs_obsolete_tab_test() {
         let line = "\tHello world";
-        let error = tabs_obsolete(line).unwrap();
-        let expected_error = Error {
-            column_number: 0,
-            error_message: "W191 indentation contains tabs".to_string()
-        };
+        let error = tabs_obsolete(line, 1).unwrap();
+        let expected_error = Diagnostic::new(Code::new("W191"), 1, 0,
+            "indentation contains tabs".to_string());
         assert_eq!(error, expected_error);
     }
 
     #[test]
     fn tabs_obsolete_base_test() {
         let line = "Hello world";
-        let error = tabs_obsolete(line);
+        let error = tabs_obsolete(line, 1);
         assert_eq!(error, None);
     }
 
     #[test]
     fn trailing_whitespace_line_test() {
         let line = "Hello world ";
-        let error = trailing_whitespace(line).unwrap();
-        let expected_error = Error {
-            error_message: "W291 trailing whitespace".to_string(),
-            column_number: 11
-        };
+        let error = trailing_whitespace(line, 1).unwrap();
+        let expected_error = Diagnostic::new(Code::new("W291"), 1, 11,
+            "trailing whitespace".to_string())
+            .with_end_column(12);
         assert_eq!(error, expected_error)
     }
 
     #[test]
     fn trailing_whitespace_blank_test() {
         let line = " ";
-        let error = trailing_whitespace(line).unwrap();
-        let expected_error = Error {
-            error_message: "W293 blank line contains whitespace".to_string(),
-            column_number: 0
-        };
+        let error = trailing_whitespace(line, 1).unwrap();
+        let expected_error = Diagnostic::new(Code::new("W293"), 1, 0,
+            "blank line contains whitespace".to_string())
+            .with_end_column(1);
         assert_eq!(error, expected_error)
     }
 
     #[test]
     fn trailing_whitespace_base_test() {
         let line = "Hello world";
-        let error = trailing_whitespace(line);
+        let error = trailing_whitespace(line, 1);
         assert_eq!(error, None)
     }
 
@@ -659,10 +1625,8 @@ mod test_checks {
         let line_number = 10;
         let total_lines = 10;
         let error = trailing_blank_lines(line, line_number, total_lines).unwrap();
-        let expected_error = Error {
-            error_message: "W292 no newline at end of file".to_string(),
-            column_number: 11
-        };
+        let expected_error = Diagnostic::new(Code::new("W292"), line_number, 11,
+            "no newline at end of file".to_string());
         assert_eq!(error, expected_error)
     }
 
@@ -673,10 +1637,8 @@ mod test_checks {
         let line_number = 10;
         let total_lines = 10;
         let error = trailing_blank_lines(line, line_number, total_lines).unwrap();
-        let expected_error = Error {
-            error_message: "W391 blank line at end of file".to_string(),
-            column_number: 0
-        };
+        let expected_error = Diagnostic::new(Code::new("W391"), line_number, 0,
+            "blank line at end of file".to_string());
         assert_eq!(error, expected_error)
     }
 
@@ -684,11 +1646,10 @@ mod test_checks {
     fn maximum_line_length_test() {
         let line = "Hello world";
         let max_line_length = 10;
-        let error = maximum_line_length(line, max_line_length).unwrap();
-        let expected_error = Error {
-            error_message: "10 ES501 line to long (11 > 10 characters)".to_string(),
-            column_number: 10
-        };
+        let error = maximum_line_length(line, 1, max_line_length).unwrap();
+        let expected_error = Diagnostic::new(Code::new("E501"), 1, 10,
+            "line too long (11 > 10 characters)".to_string())
+            .with_end_column(11);
         assert_eq!(error, expected_error)
     }
 
@@ -696,254 +1657,236 @@ mod test_checks {
     fn maximum_line_length_none_test() {
         let line = "Hello world";
         let max_line_length = 11;
-        let error = maximum_line_length(line, max_line_length);
+        let error = maximum_line_length(line, 1, max_line_length);
         assert_eq!(error, None)
     }
 
     #[test]
     fn extraneous_whitespace_after_paren() {
         let line = "spam( ham[1], {eggs: 2})";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E201 whitespace after (".to_string(),
-            column_number: 5
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E201"), 1, 5,
+            "whitespace after (".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_after_square_bracket() {
         let line = "spam(ham[ 1], {eggs: 2})";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E201 whitespace after [".to_string(),
-            column_number: 9
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E201"), 1, 9,
+            "whitespace after [".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_after_bracket() {
         let line = "spam(ham[1], { eggs: 2})";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E201 whitespace after {".to_string(),
-            column_number: 14
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E201"), 1, 14,
+            "whitespace after {".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_before_paren() {
         let line = "spam(ham[1], {eggs: 2} )";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E202 whitespace before )".to_string(),
-            column_number: 23
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E202"), 1, 22,
+            "whitespace before )".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_before_square_bracket() {
         let line = "spam(ham[1 ], {eggs: 2})";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E202 whitespace before ]".to_string(),
-            column_number: 11
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E202"), 1, 10,
+            "whitespace before ]".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_before_bracket() {
         let line = "spam(ham[1], {eggs: 2 })";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E202 whitespace before }".to_string(),
-            column_number: 22
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E202"), 1, 21,
+            "whitespace before }".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_before_comma() {
         let line = "if x == 4: print x, y; x, y = y , x";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E203 whitespace before ,".to_string(),
-            column_number: 32
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E203"), 1, 31,
+            "whitespace before ,".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_before_semi_colon() {
         let line = "if x == 4: print x, y ; x, y = y, x";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E203 whitespace before ;".to_string(),
-            column_number: 22
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E203"), 1, 21,
+            "whitespace before ;".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn extraneous_whitespace_before_colon() {
         let line = "if x == 4 : print x, y; x, y = y, x";
-        let error =  extraneous_whitespace(line);
-        let expected_error = Error {
-            error_message: "E203 whitespace before :".to_string(),
-            column_number: 10
-        };
+        let error =  extraneous_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E203"), 1, 9,
+            "whitespace before :".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn whitespace_around_keywords_space_after_and() {
         let line = "True and  False";
-        let error =  whitespace_around_keywords(line);
-        let expected_error = Error {
-            error_message: "E271 multiple spaces after keyword".to_string(),
-            column_number: 10
-        };
+        let error =  whitespace_around_keywords(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E271"), 1, 8,
+            "multiple spaces after keyword".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn whitespace_around_keywords_space_before_and() {
         let line = "True  and False";
-        let error =  whitespace_around_keywords(line);
-        let expected_error = Error {
-            error_message: "E272 multiple spaces before keyword".to_string(),
-            column_number: 4
-        };
+        let error =  whitespace_around_keywords(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E272"), 1, 4,
+            "multiple spaces before keyword".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn whitespace_around_keywords_tab_after_and() {
         let line = "True and\tFalse";
-        let error =  whitespace_around_keywords(line);
-        let expected_error = Error {
-            error_message: "E273 tab after keyword".to_string(),
-            column_number: 9
-        };
+        let error =  whitespace_around_keywords(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E273"), 1, 8,
+            "tab after keyword".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn whitespace_around_keywords_tab_before_and() {
         let line = "True\tand False";
-        let error =  whitespace_around_keywords(line);
-        let expected_error = Error {
-            error_message: "E274 tab before keyword".to_string(),
-            column_number: 4
-        };
+        let error =  whitespace_around_keywords(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E274"), 1, 4,
+            "tab before keyword".to_string());
+        assert_eq!(error, vec![Some(expected_error)])
+    }
+
+    #[test]
+    fn whitespace_around_keywords_space_after_await() {
+        let line = "x = await  y";
+        let error =  whitespace_around_keywords(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E271"), 1, 9,
+            "multiple spaces after keyword".to_string());
+        assert_eq!(error, vec![Some(expected_error)])
+    }
+
+    #[test]
+    fn whitespace_around_keywords_space_before_await() {
+        let line = "x =  await y";
+        let error =  whitespace_around_keywords(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E272"), 1, 3,
+            "multiple spaces before keyword".to_string());
         assert_eq!(error, vec![Some(expected_error)])
     }
 
     #[test]
     fn missing_whitespace_after_import_keyword_absolute_no_space() {
         let line = "from foo import(bar, baz)";
-        let error =  missing_whitespace_after_import_keyword(line);
-        let expected_error = Error {
-            error_message: "E275: missing whitespace after keyword import".to_string(),
-            column_number: 15
-        };
+        let error =  missing_whitespace_after_import_keyword(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E275"), 1, 15,
+            "missing whitespace after keyword import".to_string());
         assert_eq!(error, Some(expected_error))
     }
 
     #[test]
     fn missing_whitespace_after_import_keyword_relative_no_space() {
         let line = "from importable.module import(bar, baz)";
-        let error =  missing_whitespace_after_import_keyword(line);
-        let expected_error = Error {
-            error_message: "E275: missing whitespace after keyword import".to_string(),
-            column_number: 29
-        };
+        let error =  missing_whitespace_after_import_keyword(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E275"), 1, 29,
+            "missing whitespace after keyword import".to_string());
         assert_eq!(error, Some(expected_error))
     }
 
     #[test]
     fn missing_whitespace_after_import_keyword_base() {
         let line = "from foo import (bar, baz)";
-        let error =  missing_whitespace_after_import_keyword(line);
+        let error =  missing_whitespace_after_import_keyword(&logical_line(line));
         assert_eq!(error, None)
     }
 
     #[test]
     fn missing_whitespace_comma_okay() {
         let line = "[a, b]";
-        let error =  missing_whitespace(line);
+        let error =  missing_whitespace(&logical_line(line));
         assert_eq!(error, vec![])
     }
 
     #[test]
     fn missing_whitespace_comma_tuple_okay() {
         let line = "(3,)";
-        let error =  missing_whitespace(line);
+        let error =  missing_whitespace(&logical_line(line));
         assert_eq!(error, vec![])
     }
 
     #[test]
     fn missing_whitespace_colon_slice_okay() {
         let line = "a[1:4]";
-        let error =  missing_whitespace(line);
+        let error =  missing_whitespace(&logical_line(line));
         assert_eq!(error, vec![])
     }
 
     #[test]
     fn missing_whitespace_colon_all_before_slice_okay() {
         let line = "a[:4]";
-        let error =  missing_whitespace(line);
+        let error =  missing_whitespace(&logical_line(line));
         assert_eq!(error, vec![])
     }
 
     #[test]
     fn missing_whitespace_colon_all_after_slice_okay() {
         let line = "a[:4]";
-        let error =  missing_whitespace(line);
+        let error =  missing_whitespace(&logical_line(line));
         assert_eq!(error, vec![])
     }
 
     #[test]
     fn missing_whitespace_colon_step_slice_okay() {
         let line = "a[:4]";
-        let error =  missing_whitespace(line);
+        let error =  missing_whitespace(&logical_line(line));
         assert_eq!(error, vec![])
     }
 
     #[test]
     fn missing_whitespace_after_comma_array() {
         let line = "['a','b']";
-        let error =  missing_whitespace(line);
-        let expected_error = Error {
-            error_message: "E231 missing whitespace after ,".to_string(),
-            column_number: 4
-        };
+        let error =  missing_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E231"), 1, 4,
+            "missing whitespace after ,".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn missing_whitespace_after_comma_function() {
         let line = "foo(bar,baz)";
-        let error =  missing_whitespace(line);
-        let expected_error = Error {
-            error_message: "E231 missing whitespace after ,".to_string(),
-            column_number: 7
-        };
+        let error =  missing_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E231"), 1, 7,
+            "missing whitespace after ,".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn missing_whitespace_after_colon_dict() {
         let line = "[{'a':'b'}]";
-        let error =  missing_whitespace(line);
-        let expected_error = Error {
-            error_message: "E231 missing whitespace after :".to_string(),
-            column_number: 5
-        };
+        let error =  missing_whitespace(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E231"), 1, 5,
+            "missing whitespace after :".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
@@ -953,7 +1896,7 @@ mod test_checks {
         let previous_line = "";
         let indent_level = 0;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
         assert_eq!(error, None);
     }
@@ -964,7 +1907,7 @@ mod test_checks {
         let previous_line = "if a == 0:";
         let indent_level = 4;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
         assert_eq!(error, None);
     }
@@ -975,12 +1918,10 @@ mod test_checks {
         let previous_line = "";
         let indent_level = 3;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
-        let expected_error = Error {
-            error_message: "E111: indentation is not a multiple of four".to_string(),
-            column_number: 0,
-        };
+        let expected_error = Diagnostic::new(Code::new("E111"), 1, 3,
+            "indentation is not a multiple of four".to_string());
         assert_eq!(error, Some(expected_error));
     }
 
@@ -990,12 +1931,10 @@ mod test_checks {
         let previous_line = "";
         let indent_level = 3;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
-        let expected_error = Error {
-            error_message: "E114: indentation is not a multiple of four (comment)".to_string(),
-            column_number: 0,
-        };
+        let expected_error = Diagnostic::new(Code::new("E114"), 1, 3,
+            "indentation is not a multiple of four (comment)".to_string());
         assert_eq!(error, Some(expected_error));
     }
 
@@ -1005,7 +1944,7 @@ mod test_checks {
         let previous_line = "for item in items:";
         let indent_level = 4;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
         assert_eq!(error, None);
     }
@@ -1016,12 +1955,10 @@ mod test_checks {
         let previous_line = "for item in items:";
         let indent_level = 0;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
-        let expected_error = Error {
-            error_message: "E112: expected an indented block".to_string(),
-            column_number: 0,
-        };
+        let expected_error = Diagnostic::new(Code::new("E112"), 1, 0,
+            "expected an indented block".to_string());
         assert_eq!(error, Some(expected_error));
     }
 
@@ -1031,12 +1968,10 @@ mod test_checks {
         let previous_line = "for item in items:";
         let indent_level = 0;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
-        let expected_error = Error {
-            error_message: "E115: expected an indented block (comment)".to_string(),
-            column_number: 0,
-        };
+        let expected_error = Diagnostic::new(Code::new("E115"), 1, 0,
+            "expected an indented block (comment)".to_string());
         assert_eq!(error, Some(expected_error));
     }
 
@@ -1046,7 +1981,7 @@ mod test_checks {
         let previous_line = "a = 1";
         let indent_level = 0;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
         assert_eq!(error, None);
     }
@@ -1057,12 +1992,10 @@ mod test_checks {
         let previous_line = "a = 1";
         let indent_level = 4;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
-        let expected_error = Error {
-            error_message: "E113: unexpected indentation".to_string(),
-            column_number: 0,
-        };
+        let expected_error = Diagnostic::new(Code::new("E113"), 1, 4,
+            "unexpected indentation".to_string());
         assert_eq!(error, Some(expected_error));
     }
 
@@ -1072,139 +2005,1140 @@ mod test_checks {
         let previous_line = "a = 1";
         let indent_level = 4;
         let previous_indent_level = 0;
-        let error =  indentation(line, previous_line,
+        let error =  indentation(&logical_line(line), previous_line,
                                  indent_level, previous_indent_level);
-        let expected_error = Error {
-            error_message: "E116: unexpected indentation (comment)".to_string(),
-            column_number: 0,
-        };
+        let expected_error = Diagnostic::new(Code::new("E116"), 1, 4,
+            "unexpected indentation (comment)".to_string());
         assert_eq!(error, Some(expected_error));
     }
 
     #[test]
     fn whitespace_around_operator_okay() {
         let line = "a = 12 + 3";
-        let error =  whitespace_around_operator(line);
+        let error =  whitespace_around_operator(&logical_line(line));
         assert_eq!(error, vec![]);
     }
 
     #[test]
     fn whitespace_around_operator_extra_left() {
         let line = "a = 4  + 5";
-        let error =  whitespace_around_operator(line);
-        let expected_error = Error {
-            error_message: "E221 multiple spaces before operator".to_string(),
-            column_number: 5
-        };
+        let error =  whitespace_around_operator(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E221"), 1, 5,
+            "multiple spaces before operator".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn whitespace_around_operator_extra_right() {
         let line = "a = 4 +  5";
-        let error =  whitespace_around_operator(line);
-        let expected_error = Error {
-            error_message: "E222 multiple spaces after operator".to_string(),
-            column_number: 7
-        };
+        let error =  whitespace_around_operator(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E222"), 1, 7,
+            "multiple spaces after operator".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn whitespace_around_operator_extra_left_tab() {
         let line = "a = 4\t+ 5";
-        let error =  whitespace_around_operator(line);
-        let expected_error = Error {
-            error_message: "E223 tab before operator".to_string(),
-            column_number: 5
-        };
+        let error =  whitespace_around_operator(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E223"), 1, 5,
+            "tab before operator".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn whitespace_around_operator_extra_right_tab() {
         let line = "a = 4 +\t5";
-        let error =  whitespace_around_operator(line);
-        let expected_error = Error {
-            error_message: "E224 tab after operator".to_string(),
-            column_number: 7
-        };
+        let error =  whitespace_around_operator(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E224"), 1, 7,
+            "tab after operator".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
+    #[test]
+    fn whitespace_around_operator_inside_string_okay() {
+        let line = "a = \"x  + y\"";
+        let error =  whitespace_around_operator(&logical_line(line));
+        assert_eq!(error, vec![]);
+    }
+
+    #[test]
+    fn whitespace_around_operator_inside_comment_okay() {
+        let line = "a = 1  # x  + y";
+        let error =  whitespace_around_operator(&logical_line(line));
+        assert_eq!(error, vec![]);
+    }
+
+    #[test]
+    fn extraneous_whitespace_inside_string_okay() {
+        let line = "spam('( ham ]')";
+        let error =  extraneous_whitespace(&logical_line(line));
+        assert_eq!(error, vec![]);
+    }
+
+    #[test]
+    fn missing_whitespace_inside_string_okay() {
+        let line = "foo('bar,baz')";
+        let error =  missing_whitespace(&logical_line(line));
+        assert_eq!(error, vec![])
+    }
+
+    #[test]
+    fn whitespace_around_keywords_inside_string_okay() {
+        let line = "x = 'True and  False'";
+        let error =  whitespace_around_keywords(&logical_line(line));
+        assert_eq!(error, vec![])
+    }
+
     #[test]
     fn whitespace_around_comma_okay() {
         let line = "a = (1, 2)";
-        let error =  whitespace_around_operator(line);
+        let error =  whitespace_around_operator(&logical_line(line));
         assert_eq!(error, vec![]);
     }
 
     #[test]
     fn whitespace_around_comma_space() {
         let line = "a = (1,  2)";
-        let error =  whitespace_around_comma(line);
-        let expected_error = Error {
-            error_message: "E241 multiple spaces after ,".to_string(),
-            column_number: 7
-        };
+        let error =  whitespace_around_comma(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E241"), 1, 7,
+            "multiple spaces after ,".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn whitespace_around_comma_tab() {
         let line = "a = (1,\t2)";
-        let error =  whitespace_around_comma(line);
-        let expected_error = Error {
-            error_message: "E242 tab after ,".to_string(),
-            column_number: 7
-        };
+        let error =  whitespace_around_comma(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E242"), 1, 7,
+            "tab after ,".to_string());
         assert_eq!(error, vec![Some(expected_error)]);
     }
 
     #[test]
     fn imports_on_separate_lines_base_okay() {
         let line = "import os";
-        let error =  imports_on_separate_lines(line);
+        let error =  imports_on_separate_lines(&logical_line(line));
         assert_eq!(error, None);
     }
 
     #[test]
     fn imports_on_separate_lines_multiple_module_imports_okay() {
         let line = "from subprocess import Popen, PIPE";
-        let error =  imports_on_separate_lines(line);
+        let error =  imports_on_separate_lines(&logical_line(line));
         assert_eq!(error, None);
     }
 
     #[test]
     fn imports_on_separate_lines_single_module_import_okay() {
         let line = "from myclas import MyClass";
-        let error =  imports_on_separate_lines(line);
+        let error =  imports_on_separate_lines(&logical_line(line));
         assert_eq!(error, None);
     }
 
     #[test]
     fn imports_on_separate_lines_relative_single_module_import_okay() {
         let line = "from foo.bar.yourclass import YourClass";
-        let error =  imports_on_separate_lines(line);
+        let error =  imports_on_separate_lines(&logical_line(line));
         assert_eq!(error, None);
     }
 
     #[test]
     fn imports_on_separate_lines_relative_module_import_okay() {
         let line = "import foo.bar.yourclass";
-        let error =  imports_on_separate_lines(line);
+        let error =  imports_on_separate_lines(&logical_line(line));
         assert_eq!(error, None);
     }
 
     #[test]
     fn imports_on_separate_lines_same_line() {
         let line = "import sys, os";
-        let error =  imports_on_separate_lines(line);
-        let expected_error = Error {
-            error_message: "E401 multiple imports on one line".to_string(),
-            column_number: 10
-        };
+        let error =  imports_on_separate_lines(&logical_line(line));
+        let expected_error = Diagnostic::new(Code::new("E401"), 1, 10,
+            "multiple imports on one line".to_string());
         assert_eq!(error, Some(expected_error));
     }
+
+    #[test]
+    fn check_source_reports_logical_line_errors_on_physical_line() {
+        let source = "foo = [1,\n       2 , 3]\n";
+        let errors = check_source(source, &Options::default());
+        let expected_error = Diagnostic::new(Code::new("E203"), 2, 8,
+            "whitespace before ,".to_string());
+        assert_eq!(errors, vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_ignores_triple_quoted_string_contents() {
+        let source = "x = \"\"\"\n   a  +  b\n\"\"\"\n";
+        let errors = check_source(source, &Options::default());
+        assert_eq!(errors, vec![]);
+    }
+
+    #[test]
+    fn check_source_no_newline_at_end_of_file() {
+        let errors = check_source("x = 1", &Options::default());
+        let expected_error = Diagnostic::new(Code::new("W292"), 1, 5,
+            "no newline at end of file".to_string());
+        assert_eq!(errors, vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_token_error() {
+        let errors = check_source("x = (1,\n", &Options::default());
+        let expected_error = Diagnostic::new(Code::new("E902"), 2, 0,
+            "TokenError: EOF in multi-line statement".to_string());
+        assert_eq!(errors, vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_token_error_selection() {
+        let mut options = Options::default();
+        options.set("ignore", "E902").unwrap();
+        assert_eq!(check_source("x = (\n", &options), vec![]);
+        let codes: Vec<&str> = check_source("x = (1, \n", &Options::default()).iter()
+            .map(|error| error.code.as_str())
+            .collect();
+        assert_eq!(codes, vec!["W291", "E902"]);
+    }
+
+    #[test]
+    fn maximum_doc_length_comment() {
+        let line = logical_line("# aaaaaa bbbbbb\nx = 1\n");
+        let expected_error = Diagnostic::new(Code::new("W505"), 1, 10,
+            "doc line too long (15 > 10 characters)".to_string())
+            .with_end_column(15);
+        assert_eq!(maximum_doc_length(&line, Some(10)), vec![expected_error]);
+        assert_eq!(maximum_doc_length(&line, None), vec![]);
+    }
+
+    #[test]
+    fn maximum_doc_length_docstring() {
+        let line = logical_line("\"\"\"Short.\n\nThis line is long.\n\"\"\"\n");
+        let expected_error = Diagnostic::new(Code::new("W505"), 3, 12,
+            "doc line too long (18 > 12 characters)".to_string())
+            .with_end_column(18);
+        assert_eq!(maximum_doc_length(&line, Some(12)), vec![expected_error]);
+    }
+
+    #[test]
+    fn maximum_doc_length_ignores_code_lines() {
+        let line = logical_line("x = 'a long string here'  # and a long comment\n");
+        assert_eq!(maximum_doc_length(&line, Some(10)), vec![]);
+    }
+
+    #[test]
+    fn check_source_select_and_ignore() {
+        let source = "x = 1 \ny = [1,2]\n";
+        let mut options = Options::default();
+        options.set("ignore", "W2").unwrap();
+        let codes: Vec<&str> = check_source(source, &options).iter()
+            .map(|error| error.code.as_str())
+            .collect();
+        assert_eq!(codes, vec!["E231"]);
+        let mut options = Options::default();
+        options.set("select", "W").unwrap();
+        let codes: Vec<&str> = check_source(source, &options).iter()
+            .map(|error| error.code.as_str())
+            .collect();
+        assert_eq!(codes, vec!["W291"]);
+    }
+
+    #[test]
+    fn check_source_noqa() {
+        let source = "import os, sys  # noqa\nx = [1,2]  # NOQA: W291\ny = [1,2]  # noqa:E231\n";
+        let expected_error = Diagnostic::new(Code::new("E231"), 2, 6,
+            "missing whitespace after ,".to_string());
+        assert_eq!(check_source(source, &Options::default()), vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_noqa_applies_to_logical_line() {
+        let source = "foo(a,b,  # noqa: E231\n    c,d)\nx = 1 \\\n    + 1  # noqa\n";
+        assert_eq!(check_source(source, &Options::default()), vec![]);
+    }
+
+    #[test]
+    fn check_source_noqa_physical_line_only() {
+        let source = "x = 1  # noqa\nfoo = ''\n";
+        let mut options = Options::default();
+        options.set("max-line-length", "5").unwrap();
+        let expected_error = Diagnostic::new(Code::new("E501"), 2, 5,
+            "line too long (8 > 5 characters)".to_string())
+            .with_end_column(8);
+        assert_eq!(check_source(source, &options), vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_default_ignore() {
+        let source = "a = (1,  2)\n";
+        assert_eq!(check_source(source, &Options::default()), vec![]);
+        let mut options = Options::default();
+        options.set("select", "E24").unwrap();
+        let expected_error = Diagnostic::new(Code::new("E241"), 1, 7,
+            "multiple spaces after ,".to_string());
+        assert_eq!(check_source(source, &options), vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_max_line_length_option() {
+        let source = "x = 'aaaaaaaaaa'\n";
+        assert_eq!(check_source(source, &Options::default()), vec![]);
+        let options = Options { max_line_length: 10, ..Options::default() };
+        let expected_error = Diagnostic::new(Code::new("E501"), 1, 10,
+            "line too long (16 > 10 characters)".to_string())
+            .with_end_column(16);
+        assert_eq!(check_source(source, &options), vec![expected_error]);
+    }
+
+    #[test]
+    fn check_source_columns_count_characters() {
+        let mut options = Options::default();
+        options.set("select", "E24,E401,E501,W291,W292").unwrap();
+        options.set("max-line-length", "10").unwrap();
+        let source = "import é, ü\nx = ('é',  'üü') \ny = 'é'";
+        assert_eq!(check_source(source, &options), vec![
+            Diagnostic::new(Code::new("E401"), 1, 8, "multiple imports on one line".to_string()),
+            Diagnostic::new(Code::new("E501"), 1, 10,
+                            "line too long (11 > 10 characters)".to_string())
+                .with_end_column(11),
+            Diagnostic::new(Code::new("E241"), 2, 9, "multiple spaces after ,".to_string()),
+            Diagnostic::new(Code::new("E501"), 2, 10,
+                            "line too long (17 > 10 characters)".to_string())
+                .with_end_column(17),
+            Diagnostic::new(Code::new("W291"), 2, 16, "trailing whitespace".to_string())
+                .with_end_column(17),
+            Diagnostic::new(Code::new("W292"), 3, 7, "no newline at end of file".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn continued_indentation_examples() {
+        let mut options = Options::default();
+        options.set("select", "E12,E13").unwrap();
+        check_examples(CONTINUED_INDENTATION, &options);
+    }
+
+    #[test]
+    fn continued_indentation_hang_closing() {
+        let mut options = Options::default();
+        options.set("select", "E12,E13").unwrap();
+        options.set("hang-closing", "true").unwrap();
+        let source = "a = (\n    42\n    )\nb = (\n    42\n)\n";
+        assert_eq!(check_source(source, &options), vec![
+            Diagnostic::new(Code::new("E133"), 6, 0,
+                            "closing bracket is missing indentation".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn continued_indentation_visual_and_hanging() {
+        let mut options = Options::default();
+        options.set("select", "E12,E13").unwrap();
+        let source = "\
+foo = long_function_name(var_one, var_two,
+                         var_three, var_four)
+result = some_function_that_takes_arguments(
+    'a', 'b', 'c',
+    'd', 'e', 'f',
+)
+my_dict = {
+    'key': 'value',
+    'other': [1,
+              2],
+}
+if (this_is_one_thing and
+        that_is_another_thing):
+    do_something()
+rv.update(dict.fromkeys((
+    'qualified_name', 'name'), 1))
+print('l.%s\\t%s\\t%s\\t%r' %
+      (token[2][0], pos, tokenize.tok_name[token[0]], token[1]))
+";
+        assert_eq!(check_source(source, &options), vec![]);
+        let source = "\
+foo = long_function_name(var_one, var_two,
+    var_three, var_four)
+print('hello',
+        'world')
+if (a or
+    b):
+    pass
+";
+        assert_eq!(check_codes(source, &options),
+                   vec![(2, 4, "E128"), (4, 8, "E127"), (6, 4, "E129")]);
+    }
+
+    // Cases from the E12 testsuite of pycodestyle, with the rows and
+    // columns of the expected errors.
+    #[test]
+    fn continued_indentation_testsuite() {
+        let mut options = Options::default();
+        options.set("select", "E12,E13").unwrap();
+        let cases = vec![
+            (r#"print "E121", (
+  "dent")
+"#, vec![(2, 2, "E121")]),
+            (r#"print "E122", (
+"dent")
+"#, vec![(2, 0, "E122")]),
+            (r#"my_list = [
+    1, 2, 3,
+    4, 5, 6,
+    ]
+"#, vec![(4, 4, "E123")]),
+            (r#"print "E124", ("visual",
+               "indent_two"
+              )
+"#, vec![(3, 14, "E124")]),
+            (r#"print "E124", ("visual",
+               "indent_five"
+)
+"#, vec![(3, 0, "E124")]),
+            (r#"a = (123,
+)
+"#, vec![(2, 0, "E124")]),
+            (r#"if (row < 0 or self.moduleCount <= row
+    or col < 0 or self.moduleCount <= col):
+    raise Exception("%s,%s - %s" % (row, col, self.moduleCount))
+"#, vec![(2, 4, "E129")]),
+            (r#"print "E126", (
+            "dent")
+"#, vec![(2, 12, "E126")]),
+            (r#"print "E126", (
+        "dent")
+"#, vec![(2, 8, "E126")]),
+            (r#"print "E127", ("over-",
+                  "over-indent")
+"#, vec![(2, 18, "E127")]),
+            (r#"print "E128", ("visual",
+    "hanging")
+"#, vec![(2, 4, "E128")]),
+            (r#"print "E128", ("under-",
+              "under-indent")
+"#, vec![(2, 14, "E128")]),
+            (r#"my_list = [
+    1, 2, 3,
+    4, 5, 6,
+     ]
+"#, vec![(4, 5, "E126")]),
+            (r#"result = {
+   'key1': 'value',
+   'key2': 'value',
+}
+"#, vec![(2, 3, "E121")]),
+            (r#"rv.update(dict.fromkeys((
+    'qualified_name', 'name', 'flag'),
+        'two'))
+"#, vec![(3, 8, "E126")]),
+            (r#"for foo in """
+    abc
+    123
+    """.strip().split():
+    print(foo)
+"#, vec![(4, 4, "E125")]),
+            (r#"if True:
+    result = some_function_that_takes_arguments(
+        'a', 'b', 'c',
+        'd', 'e', 'f',
+)
+"#, vec![(5, 0, "E122")]),
+            (r#"if some_very_very_very_long_variable_name or var \
+or another_very_long_variable_name:
+    raise Exception()
+"#, vec![(2, 0, "E122")]),
+            (r#"if some_very_very_very_long_variable_name or var[0] \
+or another_very_long_variable_name:
+    raise Exception()
+"#, vec![(2, 0, "E122")]),
+            (r#"if True:
+    if some_very_very_very_long_variable_name or var \
+    or another_very_long_variable_name:
+        raise Exception()
+"#, vec![(3, 4, "E122")]),
+            (r#"if True:
+    if some_very_very_very_long_variable_name or var[0] \
+    or another_very_long_variable_name:
+        raise Exception()
+"#, vec![(3, 4, "E122")]),
+            (r#"dictionary = {
+    "is": {
+    "nested": yes(),
+    },
+}
+"#, vec![(3, 4, "E122")]),
+            (r#"setup('',
+      scripts=[''],
+      classifiers=[
+      'Development Status :: 4 - Beta',
+          'Environment :: Console',
+          'Intended Audience :: Developers',
+      ])
+"#, vec![(4, 6, "E122")]),
+            (r#"result = {
+    'foo': [
+        'bar', {
+            'baz': 'frop',
+            }
+        ]
+    }
+"#, vec![(5, 12, "E123"), (6, 8, "E123"), (7, 4, "E123")]),
+            (r#"result = some_function_that_takes_arguments(
+    'a', 'b', 'c',
+    'd', 'e', 'f',
+    )
+"#, vec![(4, 4, "E123")]),
+            (r#"my_list = [1, 2, 3,
+           4, 5, 6,
+]
+"#, vec![(3, 0, "E124")]),
+            (r#"my_list = [1, 2, 3,
+           4, 5, 6,
+                   ]
+"#, vec![(3, 19, "E124")]),
+            (r#"result = some_function_that_takes_arguments('a', 'b', 'c',
+                                            'd', 'e', 'f',
+)
+"#, vec![(3, 0, "E124")]),
+            (r#"fooff(aaaa,
+      cca(
+          vvv,
+          dadd
+      ), fff,
+)
+"#, vec![(6, 0, "E124")]),
+            (r#"fooff(aaaa,
+      ccaaa(
+          vvv,
+          dadd
+      ),
+      fff,
+)
+"#, vec![(7, 0, "E124")]),
+            (r#"d = dict('foo',
+         help="exclude files or directories which match these "
+              "comma separated patterns (default: %s)" % DEFAULT_EXCLUDE
+              )
+"#, vec![(4, 14, "E124")]),
+            (r#"if line_removed:
+    self.event(cr, uid,
+        name="Removing the option for contract",
+        description="contract line has been removed",
+        )
+"#, vec![(3, 8, "E128"), (4, 8, "E128"), (5, 8, "E124")]),
+            (r#"if foo is None and bar is "frop" and \
+    blah == 'yeah':
+    blah = 'yeahnah'
+"#, vec![(2, 4, "E125")]),
+            (r#"def long_function_name(
+    var_one, var_two, var_three,
+    var_four):
+    print(var_one)
+"#, vec![(3, 4, "E125")]),
+            (r#"def qualify_by_address(
+    self, cr, uid, ids, context=None,
+    params_to_check=frozenset(QUALIF_BY_ADDRESS_PARAM)):
+    """ This gets called by the web server """
+"#, vec![(3, 4, "E125")]),
+            (r#"my_list = [
+    1, 2, 3,
+    4, 5, 6,
+        ]
+"#, vec![(4, 8, "E126")]),
+            (r#"abris = 3 + \
+        4 + \
+        5 + 6
+"#, vec![(2, 8, "E126")]),
+            (r#"fixed = re.sub(r'\t+', ' ', target[c::-1], 1)[::-1] + \
+        target[c + 1:]
+"#, vec![(2, 8, "E126")]),
+            (r#"rv.update(dict.fromkeys((
+            'qualified_name', 'name', 'flag'),
+        'two'))
+"#, vec![(2, 12, "E126"), (3, 8, "E126")]),
+            (r#"eat_a_dict_a_day({
+        "foo": "bar",
+})
+"#, vec![(2, 8, "E126")]),
+            (r#"if (
+    x == (
+            3
+    )
+    or y == 4):
+    pass
+"#, vec![(3, 12, "E126"), (5, 4, "E129")]),
+            (r#"if (
+    x == (
+        3
+    )
+    or x == (
+            3)
+    or y == 4):
+    pass
+"#, vec![(6, 12, "E126"), (7, 4, "E129")]),
+            (r#"troublesome_hash = {
+    "hash": "value",
+    "long": "the quick brown fox jumps over the lazy dog before doing a "
+        "somersault",
+}
+"#, vec![(4, 8, "E131")]),
+            (r#"foo = long_function_name(var_one, var_two,
+    var_three, var_four)
+"#, vec![(2, 4, "E128")]),
+            (r#"print('l.%s\t%s\t%s\t%r' %
+    (token[2][0], pos, tokenize.tok_name[token[0]], token[1]))
+"#, vec![(2, 4, "E128")]),
+            (r#"def qualify_by_address(self, cr, uid, ids, context=None,
+        params_to_check=frozenset(QUALIF_BY_ADDRESS_PARAM)):
+    """ This gets called by the web server """
+"#, vec![(2, 8, "E128")]),
+            (r#"foo(1, 2, 3,
+4, 5, 6)
+"#, vec![(2, 0, "E128")]),
+            (r#"foo(1, 2, 3,
+ 4, 5, 6)
+"#, vec![(2, 1, "E128")]),
+            (r#"foo(1, 2, 3,
+     4, 5, 6)
+"#, vec![(2, 5, "E127")]),
+            (r#"foo(1, 2, 3,
+             4, 5, 6)
+"#, vec![(2, 13, "E127")]),
+            (r#"rv.update(d=('a', 'b', 'c'),
+             e=42)
+"#, vec![(2, 13, "E127")]),
+            (r#"rv.update(d=('a' + 'b', 'c'),
+          e=42, f=42
+                 + 42)
+"#, vec![(3, 17, "E127")]),
+            (r#"input1 = {'a': {'calc': 1 + 2}, 'b': 1
+                          + 42}
+"#, vec![(2, 26, "E127")]),
+            (r#"rv.update(d=('a' + 'b', 'c'),
+          e=42, f=(42
+                 + 42))
+"#, vec![(3, 17, "E128")]),
+            (r#"if True:
+    def example_issue254():
+        return [node.copy(
+            (
+                replacement
+                # First, look at all the node's current children.
+                for child in node.children
+                # Replace them.
+                for replacement in replace(child)
+                ),
+            dict(name=token.undefined)
+        )]
+"#, vec![(10, 16, "E123")]),
+            (r#"if ("""
+    """):
+    pass
+
+for foo in """
+    abc
+    123
+    """.strip().split():
+    print(foo)
+"#, vec![(2, 4, "E125"), (8, 4, "E125")]),
+            (r#"print dedent(
+    '''
+        mkdir -p ./{build}/
+        mv ./build/ ./{build}/%(revision)s/
+    '''.format(
+    build='build',
+    # more stuff
+)
+)
+"#, vec![(6, 4, "E122"), (7, 4, "E122"), (8, 0, "E122")]),
+            (r#"def foo():
+    pass
+    raise 123 + \
+           123
+"#, vec![(4, 11, "E127")]),
+            (r#"class Eggs:
+    pass
+    assert 123456 == \
+            123456
+"#, vec![(4, 12, "E127")]),
+            (r#"def f1():
+    print('foo')
+    with open('/path/to/some/file/you/want/to/read') as file_1, \
+          open('/path/to/some/file/being/written', 'w') as file_2:
+        file_2.write(file_1.read())
+"#, vec![(4, 10, "E127")]),
+            (r#"def f1():
+    print('foo')
+    with open('/path/to/some/file/you/want/to/read') as file_1, \
+         open('/path/to/some/file/being/written', 'w') as file_2, \
+          open('later-misindent'):
+        file_2.write(file_1.read())
+"#, vec![(5, 10, "E127")]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(check_codes(source, &options), expected, "{}", source);
+        }
+    }
+
+    // Cases from the E12not testsuite of pycodestyle.
+    #[test]
+    fn continued_indentation_testsuite_okay() {
+        let mut options = Options::default();
+        options.set("select", "E12,E13").unwrap();
+        let source = r#"if (
+        x == (
+            3
+        ) or
+        y == 4):
+    pass
+
+y = x == 2 \
+    or x == 3
+
+if x == 2 \
+    or y > 1 \
+        or x == 3:
+    pass
+
+if x == 2 \
+        or y > 1 \
+        or x == 3:
+    pass
+
+if (foo == bar and
+        baz == bop):
+    pass
+
+if (
+    foo == bar and
+    baz == bop
+):
+    pass
+
+a = (
+)
+
+a = (123,
+     )
+
+if start[1] > end_col and not (
+        over_indent == 4 and indent_next):
+    return (0, "E121 continuation line over-"
+            "indented for visual indent")
+
+print "OK", ("visual",
+             "indent")
+
+print "Okay", ("visual",
+               "indent_three"
+               )
+
+print "a-ok", (
+    "there",
+    "dude",
+)
+
+print "hello", (
+    "there",
+    "dude")
+
+print "hello", (
+
+    "there",
+    # "john",
+    "dude")
+
+arm = 'AAA'    \
+      'BBB'    \
+      'CCC'
+
+bbb = 'AAA'    \
+    'BBB'    \
+    'CCC'
+
+cc = {'text': 'AAA'
+              'BBB'
+              'CCC'}
+
+cc = dict(text='AAA'
+               'BBB')
+
+abricot = 3 + \
+    4 + \
+    5 + 6
+
+part = [-1, (2, 3,
+             4, 5, 6), 7,
+        8, 9, 0]
+
+
+def long_function_name(
+        var_one, var_two, var_three,
+        var_four):
+    print(var_one)
+
+
+if ((row < 0 or self.moduleCount <= row or
+     col < 0 or self.moduleCount <= col)):
+    raise Exception("%s,%s - %s" % (row, col, self.moduleCount))
+
+result = {
+    'foo': [
+        'bar', {
+            'baz': 'frop',
+        }
+    ]
+}
+
+foo = my.func({
+    "foo": "bar",
+}, "baz")
+
+fooff(aaaa,
+      cca(
+          vvv,
+          dadd
+      ), fff,
+      ggg)
+
+if bar:
+    return (
+        start, 'E121 lines starting with a '
+        'closing bracket should be indented '
+        "to match that of the opening "
+        "bracket's line"
+    )
+
+if ((foo.bar("baz") and
+     foo.bar("frop")
+     )):
+    print "yes"
+
+if (a == 2 or
+    b == "abc def ghi"
+         "jkl mno"):
+    return True
+
+if (a == 2 or
+    b == """abc def ghi
+jkl mno"""):
+    return True
+
+if length > options.max_line_length:
+    return options.max_line_length, \
+        "E501 line too long (%d characters)" % length
+
+print('%-7d %s per second (%d total)' % (
+      options.counters[key] / elapsed, key,
+      options.counters[key]))
+
+if foo is None and bar is "frop" and \
+        blah == 'yeah':
+    blah = 'yeahnah'
+
+if blah:
+    multiline_literal = (
+        """
+while True:
+    if True:
+        1
+"""
+        .lstrip()
+    )
+
+if blah:
+    multiline_visual = ("""
+while True:
+    if True:
+        1
+"""
+                        .lstrip())
+
+event_obj.write(cursor, user_id, {
+    'user': user,
+    'summary': text,
+    'data': {'timestamp': now, 'content': {
+        'aaa': 1,
+        'bbb': 2
+    }},
+})
+
+parser.add_option('--exclude', metavar='patterns', default=DEFAULT_EXCLUDE,
+                  help="exclude files or directories which match these "
+                       "comma separated patterns (default: %s)" %
+                       DEFAULT_EXCLUDE)
+
+add_option('--count',
+           #
+           help="print total number of errors "
+           "to standard error total is not null")
+
+help = "print total number of errors " \
+       "to standard error"
+
+d = {  # comment
+    1: 2
+}
+
+[
+    12,  # this is a multi-line inline
+         # comment
+]
+
+if a > b and \
+   c > d:
+    moo_like_a_cow()
+
+dica = {
+    ('abc'
+     'def'): (
+        'abc'),
+}
+
+(abcdef[0]
+       [1]) = (
+    'abc')
+
+bar(
+    1).zap(
+    2)
+
+
+def valid_example():
+    return [node.copy(properties=dict(
+        (key, val if val is not None else token.undefined)
+        for key, val in node.items()
+    ))]
+
+
+foo([
+    'bug'
+])
+
+some_hash = {
+    "long key that tends to happen more when you're indented":
+        "stringwithalongtoken you don't want to break",
+}
+
+{
+    1:
+        999999 if True
+        else 0,
+}
+
+print dedent(
+    '''
+        mkdir -p ./{build}/
+        mv ./build/ ./{build}/%(revision)s/
+    '''.format(
+        build='build',
+        # more stuff
+    )
+)
+"#;
+        assert_eq!(check_source(source, &options), vec![]);
+    }
+
+    #[test]
+    fn blank_lines_examples() {
+        let mut options = Options::default();
+        options.set("select", "E3").unwrap();
+        check_examples(BLANK_LINES, &options);
+    }
+
+    #[test]
+    fn blank_lines_decorators_and_comments() {
+        let mut options = Options::default();
+        options.set("select", "E3").unwrap();
+        let source = "\
+import os
+
+
+# A comment directly above the decorator.
+@decorator
+
+@other
+def a():
+    pass
+class B:
+
+    def c(self):
+        pass
+    # The comment counts as part of the method.
+    def d(self):
+        pass
+x = 1
+";
+        let errors: Vec<(usize, String)> = check_source(source, &options).iter()
+            .map(|error| (error.line, error.to_string()))
+            .collect();
+        assert_eq!(errors, vec![
+            (7, "E304 blank lines found after function decorator (1)".to_string()),
+            (10, "E302 expected 2 blank lines, found 0".to_string()),
+            (15, "E301 expected 1 blank line, found 0".to_string()),
+            (17, "E305 expected 2 blank lines after class or function definition, found 0"
+             .to_string()),
+        ]);
+    }
+
+    #[test]
+    fn blank_lines_one_liners() {
+        let mut options = Options::default();
+        options.set("select", "E3").unwrap();
+        let source = "def a(): pass\ndef b(): pass\n\n\nclass C: pass\nclass D: pass\n";
+        assert_eq!(check_source(source, &options), vec![]);
+    }
+
+    #[test]
+    fn missing_whitespace_around_operator_examples() {
+        let mut options = Options::default();
+        options.set("select", "E22").unwrap();
+        check_examples(MISSING_WHITESPACE_AROUND_OPERATOR, &options);
+    }
+
+    #[test]
+    fn missing_whitespace_around_operator_unary_and_keywords() {
+        let mut options = Options::default();
+        options.set("select", "E22").unwrap();
+        let source = "\
+x = -1
+y = x[-1] + (-x) * -2
+print(*args, **kwargs)
+def f(a, *, b=1, **kw) -> None:
+    return -a if not b else +b
+z = lambda *a, **k: a
+c = a or-b
+d = 1 -1
+e = 2**-x
+";
+        let errors: Vec<(usize, usize, String)> = check_source(source, &options).iter()
+            .map(|error| (error.line, error.column, error.to_string()))
+            .collect();
+        assert_eq!(errors, vec![
+            (7, 8, "E225 missing whitespace around operator".to_string()),
+            (8, 7, "E225 missing whitespace around operator".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn missing_whitespace_around_operator_cases() {
+        let mut options = Options::default();
+        options.set("select", "E22").unwrap();
+        assert_eq!(check_codes("x = x*2 -1\n", &options), vec![(1, 5, "E226"), (1, 9, "E225")]);
+        assert_eq!(check_codes("_1kB = _1MB >>10\n", &options), vec![(1, 14, "E225")]);
+        assert_eq!(check_codes("hypot2 = x*x+ y*y\n", &options),
+                   vec![(1, 10, "E226"), (1, 12, "E225"), (1, 15, "E226")]);
+        assert_eq!(check_codes("a = b@c\n", &options), vec![(1, 5, "E226")]);
+        assert_eq!(check_codes("async def f():\n    x = await -y\n", &options), vec![]);
+    }
+
+    #[test]
+    fn whitespace_around_default_equals_examples() {
+        let mut options = Options::default();
+        options.set("select", "E25").unwrap();
+        check_examples(WHITESPACE_AROUND_DEFAULT_EQUALS, &options);
+    }
+
+    #[test]
+    fn whitespace_around_default_equals_annotations() {
+        let mut options = Options::default();
+        options.set("select", "E25").unwrap();
+        let source = "\
+def f(a: int = 1, *, b=[x == 2], c: Dict[str, int] = {}) -> Dict[str, int]:
+    return g(a, key=lambda y=1: y)
+def h(a: int=1, b = 2) -> int:
+    pass
+";
+        let errors: Vec<(usize, usize, String)> = check_source(source, &options).iter()
+            .map(|error| (error.line, error.column, error.to_string()))
+            .collect();
+        assert_eq!(errors, vec![
+            (3, 12, "E252 missing whitespace around parameter equals".to_string()),
+            (3, 13, "E252 missing whitespace around parameter equals".to_string()),
+            (3, 17, "E251 unexpected spaces around keyword / parameter equals".to_string()),
+            (3, 19, "E251 unexpected spaces around keyword / parameter equals".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn whitespace_around_default_equals_cases() {
+        let mut options = Options::default();
+        options.set("select", "E25").unwrap();
+        // A line break after the equals is reported at its end.
+        assert_eq!(check_codes("foo(bar, default=\n    \"x\")\n", &options),
+                   vec![(1, 17, "E251")]);
+        assert_eq!(check_codes("def foo(a: int=0, b=1) -> AnyStr:\n    pass\n", &options),
+                   vec![(1, 14, "E252"), (1, 15, "E252")]);
+        assert_eq!(check_codes("async def f(x: AnyStr=None, y=2) -> AnyStr:\n    pass\n",
+                               &options),
+                   vec![(1, 21, "E252"), (1, 22, "E252")]);
+        assert_eq!(check_codes("def f(bar=1) -> AnyStr: pass\n", &options), vec![]);
+    }
+
+    #[test]
+    fn whitespace_before_comment_examples() {
+        let mut options = Options::default();
+        options.set("select", "E26").unwrap();
+        check_examples(WHITESPACE_BEFORE_COMMENT, &options);
+    }
+
+    #[test]
+    fn whitespace_before_comment_exemptions() {
+        let mut options = Options::default();
+        options.set("select", "E26").unwrap();
+        let source = "\
+#!/usr/bin/env python
+#: E101
+x = '# not a comment'  # comment
+####
+y = [1,  # one
+     2]# two
+#!not a shebang
+";
+        let errors: Vec<(usize, usize, String)> = check_source(source, &options).iter()
+            .map(|error| (error.line, error.column, error.to_string()))
+            .collect();
+        assert_eq!(errors, vec![
+            (6, 7, "E261 at least two spaces before inline comment".to_string()),
+            (7, 0, "E265 block comment should start with '# '".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn whitespace_before_comment_cases() {
+        let mut options = Options::default();
+        options.set("select", "E26").unwrap();
+        assert_eq!(check_codes("## x\n", &options), vec![(1, 0, "E266")]);
+        assert_eq!(check_codes("x = 1  #!x\n", &options), vec![(1, 7, "E262")]);
+        assert_eq!(check_codes("#!shebang\nx = 1\n", &options), vec![]);
+        assert_eq!(check_codes("x = 1 # c\n", &options), vec![(1, 5, "E261")]);
+    }
+
+    #[test]
+    fn check_file_missing_file() {
+        let error = check_file(Path::new("does/not/exist.py"), &Options::default());
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn check_source_with_custom_checks() {
+        let mut registry = Registry::new();
+        registry.register_logical_line(LogicalLineFn::new(
+            "no_print", &["X100"],
+            |context| context.logical_line.text.find("print(").into_iter()
+                .map(|offset| context.logical_line.diagnostic(
+                    offset, Code::new("X100"), "print found".to_string()))
+                .collect()));
+        registry.register_token(TokenFn::new(
+            "token_count", &["X200"],
+            |context| vec![Diagnostic::new(Code::new("X200"), 1, 0,
+                                           format!("{} tokens", context.tokens.len()))]));
+        let errors = check_source_with("if x:\n    print( 1)\n", &Options::default(),
+                                        &registry);
+        assert_eq!(errors, vec![
+            Diagnostic::new(Code::new("X200"), 1, 0, "12 tokens".to_string()),
+            Diagnostic::new(Code::new("X100"), 2, 4, "print found".to_string()),
+        ]);
+    }
 }
 
 
@@ -1214,34 +3148,41 @@ mod test_utils {
     use super::*;
 
     #[test]
-    fn calculate_indent_level_space_one() {
-        let indent_char = ' ';
-        let line = " Hello World";
-        let indent_level = calculate_indent_level(line, indent_char);
-        assert_eq!(1, indent_level);
+    fn expand_indent_spaces() {
+        assert_eq!(expand_indent("    "), 4);
+        assert_eq!(expand_indent("Hello World"), 0);
     }
 
     #[test]
-    fn calculate_indent_level_tab_one() {
-        let indent_char = '\t';
-        let line = "\tHello World";
-        let indent_level = calculate_indent_level(line, indent_char);
-        assert_eq!(1, indent_level);
+    fn expand_indent_tabs() {
+        assert_eq!(expand_indent("\t"), 8);
+        assert_eq!(expand_indent("       \t"), 8);
+        assert_eq!(expand_indent("        \t"), 16);
+        assert_eq!(expand_indent("  \tHello World"), 8);
     }
 
     #[test]
-    fn calculate_indent_level_mix_one() {
-        let indent_char = ' ';
-        let line = "  \tHello World";
-        let indent_level = calculate_indent_level(line, indent_char);
-        assert_eq!(2, indent_level);
+    fn tab_indented_block() {
+        let source = "if x:\n\tpass\n";
+        let codes: Vec<String> = check_source(source, &Options::default()).into_iter()
+            .map(|error| error.code.to_string())
+            .collect();
+        assert_eq!(codes, vec!["W191"]);
     }
 
     #[test]
-    fn calculate_indent_level_none() {
-        let indent_char = ' ';
-        let line = "Hello World";
-        let indent_level = calculate_indent_level(line, indent_char);
-        assert_eq!(0, indent_level);
+    fn detect_indent_char_first_indented_line() {
+        assert_eq!(detect_indent_char(&["if x:\n", "\tif y:\n", "\t    pass\n"]), Some('\t'));
+        assert_eq!(detect_indent_char(&["x = 1\n", "\n", "  y\n"]), Some(' '));
+        assert_eq!(detect_indent_char(&["x = 1\n"]), None);
+    }
+
+    #[test]
+    fn tabs_or_spaces_after_tab_indent() {
+        let source = "if x:\n\tpass\nif y:\n    pass\n";
+        let codes: Vec<String> = check_source(source, &Options::default()).into_iter()
+            .map(|error| format!("{}:{}", error.line, error.code))
+            .collect();
+        assert_eq!(codes, vec!["2:W191", "4:E101"]);
     }
 }
\ No newline at end of file
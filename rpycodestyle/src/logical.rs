@@ -0,0 +1,348 @@
+use diagnostic::{Code, Diagnostic};
+use tokenizer::{Token, TokenType};
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct LogicalLine {
+    pub tokens: Vec<Token>,
+    // The source of the logical line with comments removed, strings replaced
+    // by "xxx" and physical lines joined across brackets and backslashes.
+    pub text: String,
+    // (offset in text, (row, column)) pairs, sorted by offset, used to map an
+    // offset in the logical line back to a physical position.
+    pub mapping: Vec<(usize, (usize, usize))>,
+    pub blank_lines: usize,
+    pub blank_before: usize,
+    spans: Vec<(usize, usize, usize)>,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct LogicalToken<'a> {
+    pub token: &'a Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LogicalLine {
+    pub fn code_tokens(&self) -> Vec<LogicalToken<'_>> {
+        self.spans.iter().map(|&(index, start, end)| LogicalToken {
+            token: &self.tokens[index],
+            start,
+            end,
+        }).collect()
+    }
+
+    pub fn whitespace_between(&self, first: &LogicalToken, second: &LogicalToken) -> String {
+        if first.end >= second.start {
+            return String::new()
+        }
+        self.text.chars().skip(first.end).take(second.start - first.end).collect()
+    }
+
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let index = self.mapping.iter()
+            .rposition(|&(token_offset, _)| token_offset <= offset)
+            .unwrap_or(0);
+        let (token_offset, (row, column)) = self.mapping[index];
+        (row, column + offset.saturating_sub(token_offset))
+    }
+
+    pub fn diagnostic(&self, offset: usize, code: Code, message: String) -> Diagnostic {
+        let (row, column) = self.position(offset);
+        Diagnostic::new(code, row, column, message)
+    }
+
+    pub fn start(&self) -> (usize, usize) {
+        self.mapping[0].1
+    }
+
+    // The first and last physical rows the logical line spans.
+    pub fn rows(&self) -> (usize, usize) {
+        let start = self.start().0;
+        let end = self.tokens.iter()
+            .filter(|token| token.token_type != TokenType::Dedent &&
+                    token.token_type != TokenType::EndMarker)
+            .map(|token| token.end.0)
+            .max()
+            .unwrap_or(start);
+        (start, end.max(start))
+    }
+
+    pub fn physical_line(&self) -> &str {
+        let row = self.start().0;
+        self.tokens.iter()
+            .find(|token| token.start.0 == row && !token.line.is_empty())
+            .map_or("", |token| token.line.as_str())
+    }
+}
+
+pub fn build_logical_lines(tokens: &[Token]) -> Vec<LogicalLine> {
+//    Split a token stream into logical lines.
+//
+//    A logical line ends at a NEWLINE token, or at an NL token outside of
+//    brackets when the line only holds a comment.  Lines that are entirely
+//    blank are not logical lines, they are counted in blank_lines of the
+//    following one.
+    let mut logical_lines = Vec::new();
+    let mut current: Vec<Token> = Vec::new();
+    let mut parens = 0;
+    let mut blank_lines = 0;
+    let mut blank_before = 0;
+
+    for token in tokens {
+        current.push(token.clone());
+        match token.token_type {
+            TokenType::Op => {
+                if token.text == "(" || token.text == "[" || token.text == "{" {
+                    parens += 1;
+                } else if (token.text == ")" || token.text == "]" || token.text == "}") &&
+                    parens > 0 {
+                    parens -= 1;
+                }
+            }
+            TokenType::Newline | TokenType::NL if parens == 0 => {
+                if token.token_type == TokenType::NL && current.len() == 1 {
+                    // The physical line contains only this token.
+                    blank_lines += 1;
+                    current.clear();
+                    continue
+                }
+                blank_before = blank_before.max(blank_lines);
+                let tokens = current.split_off(0);
+                if let Some(line) = build_logical_line(tokens, blank_lines, blank_before) {
+                    logical_lines.push(line);
+                }
+                blank_lines = 0;
+                if token.token_type == TokenType::Newline {
+                    blank_before = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+    if !current.is_empty() {
+        blank_before = blank_before.max(blank_lines);
+        if let Some(line) = build_logical_line(current, blank_lines, blank_before) {
+            logical_lines.push(line);
+        }
+    }
+    logical_lines
+}
+
+fn build_logical_line(tokens: Vec<Token>, blank_lines: usize,
+                      blank_before: usize) -> Option<LogicalLine> {
+    let mut text = String::new();
+    let mut length = 0;
+    let mut mapping = Vec::new();
+    let mut spans = Vec::new();
+    let mut previous: Option<&Token> = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token.token_type {
+            TokenType::Newline | TokenType::NL | TokenType::Indent |
+            TokenType::Dedent | TokenType::EndMarker => continue,
+            _ => (),
+        }
+        if mapping.is_empty() {
+            mapping.push((0, token.start));
+        }
+        if token.token_type == TokenType::Comment {
+            continue
+        }
+
+        let token_text = if token.token_type == TokenType::String {
+            mute_string(&token.text)
+        } else {
+            token.text.clone()
+        };
+        let mut prefix = String::new();
+        if let Some(previous) = previous {
+            if previous.end.0 != token.start.0 {
+                let previous_char = previous.text.chars().last().unwrap_or(' ');
+                if previous_char == ',' ||
+                    (!"{[(".contains(previous_char) &&
+                     token_text != "}" && token_text != "]" && token_text != ")") {
+                    prefix.push(' ');
+                }
+            } else if previous.end.1 < token.start.1 {
+                prefix = token.line.chars()
+                    .skip(previous.end.1)
+                    .take(token.start.1 - previous.end.1)
+                    .collect();
+            }
+        }
+
+        let start = length + prefix.chars().count();
+        let end = start + token_text.chars().count();
+        mapping.push((start, token.start));
+        mapping.push((end, token.end));
+        spans.push((index, start, end));
+        text.push_str(&prefix);
+        text.push_str(&token_text);
+        length = end;
+        previous = Some(token);
+    }
+
+    if mapping.is_empty() {
+        return None
+    }
+    Some(LogicalLine {
+        tokens,
+        text,
+        mapping,
+        blank_lines,
+        blank_before,
+        spans,
+    })
+}
+
+fn mute_string(text: &str) -> String {
+//    Replace contents with 'xxx' to prevent syntax matching.
+//
+//    mute_string('"abc"') -> '"xxx"'
+//    mute_string("'''abc'''") -> "'''xxx'''"
+//    mute_string("r'abc'") -> "r'xxx'"
+    let chars: Vec<char> = text.chars().collect();
+    let last = match chars.last() {
+        Some(&last) => last,
+        None => return String::new(),
+    };
+    let mut start = chars.iter().position(|&char| char == last).unwrap() + 1;
+    let mut end = chars.len() - 1;
+    if chars.len() >= start + 5 && text.ends_with(&last.to_string().repeat(3)) {
+        start += 2;
+        end -= 2;
+    }
+    if end < start {
+        return text.to_string()
+    }
+    let mut muted: String = chars[..start].iter().collect();
+    muted.extend(std::iter::repeat_n('x', end - start));
+    muted.extend(&chars[end..]);
+    muted
+}
+
+#[cfg(test)]
+mod test_logical {
+    use super::*;
+    use tokenizer::tokenize;
+
+    fn logical_lines(source: &str) -> Vec<LogicalLine> {
+        build_logical_lines(&tokenize(source).unwrap())
+    }
+
+    #[test]
+    fn mute_string_double_quotes() {
+        assert_eq!(mute_string("\"abc\""), "\"xxx\"");
+    }
+
+    #[test]
+    fn mute_string_triple_quotes() {
+        assert_eq!(mute_string("'''abc'''"), "'''xxx'''");
+    }
+
+    #[test]
+    fn mute_string_prefix() {
+        assert_eq!(mute_string("r'abc'"), "r'xxx'");
+    }
+
+    #[test]
+    fn mute_string_empty() {
+        assert_eq!(mute_string("''"), "''");
+        assert_eq!(mute_string("''''''"), "''''''");
+    }
+
+    #[test]
+    fn logical_line_single_statement() {
+        let lines = logical_lines("a = 1\n");
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "a = 1");
+    }
+
+    #[test]
+    fn logical_line_strips_indent_and_comments() {
+        let lines = logical_lines("if a:\n    b = 'x, y'  # c\n");
+        assert_eq!(lines[1].text, "b = 'xxxx'");
+        assert_eq!(lines[1].start(), (2, 4));
+    }
+
+    #[test]
+    fn logical_line_joins_brackets() {
+        let lines = logical_lines("foo(a,\n    b)\n");
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "foo(a, b)");
+    }
+
+    #[test]
+    fn logical_line_joins_after_open_bracket() {
+        let lines = logical_lines("foo(\n    a\n)\n");
+        assert_eq!(lines[0].text, "foo(a)");
+    }
+
+    #[test]
+    fn logical_line_joins_backslash() {
+        let lines = logical_lines("a = 1 + \\\n    2\n");
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "a = 1 + 2");
+    }
+
+    #[test]
+    fn logical_line_masks_triple_quoted_string() {
+        let lines = logical_lines("x = '''a\nb'''\ny = 2\n");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "x = '''xxx'''");
+        assert_eq!(lines[1].text, "y = 2");
+    }
+
+    #[test]
+    fn logical_line_position_maps_to_physical_lines() {
+        let lines = logical_lines("foo(a,\n    b ,c)\n");
+        let line = &lines[0];
+        assert_eq!(line.text, "foo(a, b ,c)");
+        assert_eq!(line.position(0), (1, 0));
+        assert_eq!(line.position(4), (1, 4));
+        assert_eq!(line.position(7), (2, 4));
+        assert_eq!(line.position(8), (2, 5));
+        assert_eq!(line.position(9), (2, 6));
+    }
+
+    #[test]
+    fn logical_line_code_token_offsets() {
+        let lines = logical_lines("foo(a,\n    b)\n");
+        let tokens = lines[0].code_tokens();
+        let offsets: Vec<(String, usize, usize)> = tokens.iter()
+            .map(|token| (token.token.text.clone(), token.start, token.end))
+            .collect();
+        assert_eq!(offsets, vec![
+            ("foo".to_string(), 0, 3),
+            ("(".to_string(), 3, 4),
+            ("a".to_string(), 4, 5),
+            (",".to_string(), 5, 6),
+            ("b".to_string(), 7, 8),
+            (")".to_string(), 8, 9),
+        ]);
+        assert_eq!(lines[0].whitespace_between(&tokens[3], &tokens[4]), " ");
+    }
+
+    #[test]
+    fn logical_line_rows() {
+        let lines = logical_lines("x = 1\nfoo(a,  # c\n    b)\n");
+        assert_eq!(lines[0].rows(), (1, 1));
+        assert_eq!(lines[1].rows(), (2, 3));
+    }
+
+    #[test]
+    fn logical_line_comment_only_line() {
+        let lines = logical_lines("# hello\na = 1\n");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "");
+        assert_eq!(lines[0].start(), (1, 0));
+    }
+
+    #[test]
+    fn logical_line_counts_blank_lines() {
+        let lines = logical_lines("a = 1\n\n\n# c\n\nb = 2\n");
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[1].blank_lines, lines[1].blank_before), (2, 2));
+        assert_eq!((lines[2].blank_lines, lines[2].blank_before), (1, 2));
+    }
+}
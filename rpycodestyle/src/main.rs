@@ -1,41 +1,103 @@
 use std::env;
-use std::fs::File;
-use std::io::prelude::*;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process;
 
 extern crate rpycodestyle;
-use rpycodestyle::reporting;
+use rpycodestyle::cli::{parse_args, USAGE};
+use rpycodestyle::config::{read_config, Configuration};
+use rpycodestyle::files::FileFinder;
+use rpycodestyle::report::{write_explanation, Report};
+use rpycodestyle::{check_source, Code, Diagnostic, Options, Registry};
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", USAGE.lines().next().unwrap_or(""));
+    eprintln!();
+    eprintln!("rpycodestyle: error: {}", message);
+    process::exit(2);
+}
 
 fn main() {
-    let path = env::args().nth(1).unwrap();
-    let mut file = File::open(&path).expect("File not found");
-    let mut content = String::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let arguments = parse_args(&args).unwrap_or_else(|error| usage_error(&error));
+    if arguments.help {
+        print!("{}", USAGE);
+        return
+    }
+    if arguments.version {
+        println!("{}", env!("CARGO_PKG_VERSION"));
+        return
+    }
+    if let Some(ref code) = arguments.explain {
+        let registry = Registry::default();
+        let rule = registry.rule(code)
+            .unwrap_or_else(|| usage_error(&format!("unknown code: {}", code)));
+        write_explanation(&mut io::stdout(), &rule).expect("Could not write explanation");
+        return
+    }
 
-    file.read_to_string(&mut content);
-    let mut blank_lines = 0;
-    let total_lines = content.lines().count();
-    for (index, line) in content.lines().enumerate() {
-        blank_lines = increment_blank_lines(line, &blank_lines);
-        if index > 0 {
-            let previous_line = content.lines().nth(index - 1).unwrap();
-            reporting(&path, index + 1, line, total_lines, previous_line,
-                      blank_lines);
+    let mut base = Options::default();
+    let user_config = arguments.config.as_ref().map(Path::new);
+    match read_config(&mut base, &arguments.paths, user_config) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
         }
-        else {
-            reporting(&path, index + 1, line, total_lines, "", blank_lines);
+        Err(error) => usage_error(&error),
+    }
+    let mut configuration = Configuration::new(base, arguments.options.clone())
+        .unwrap_or_else(|error| usage_error(&error));
+    let options = configuration.options.clone();
+    Report::validate(&options).unwrap_or_else(|error| usage_error(&error));
+    if arguments.paths.is_empty() {
+        usage_error("input not specified");
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut report = Report::new(&options);
+    for path in &FileFinder::new(&options).find(&arguments.paths) {
+        let file_options = match configuration.for_file(Path::new(path)) {
+            Ok(Some(file_options)) => file_options,
+            Ok(None) => continue,
+            Err(error) => usage_error(&error),
+        };
+        for warning in configuration.warnings.drain(..) {
+            eprintln!("{}", warning);
+        }
+        let (source, diagnostics) = match fs::read_to_string(path) {
+            Ok(source) => {
+                let diagnostics = check_source(&source, &file_options);
+                (source, diagnostics)
+            }
+            Err(error) => {
+                let message = format!("IOError: {}", error);
+                let diagnostics = vec![Diagnostic::new(Code::new("E902"), 1, 0, message)];
+                (String::new(), diagnostics.into_iter()
+                 .filter(|diagnostic| !file_options.ignore_code(diagnostic.code))
+                 .collect())
+            }
+        };
+        report.file(&mut out, path, &source, &diagnostics).expect("Could not write report");
+    }
+    report.finish(&mut out).expect("Could not write report");
+    out.flush().expect("Could not write report");
+    if let Some(ref output_file) = options.output_file {
+        let written = fs::File::create(output_file)
+            .and_then(|mut file| report.write_document(&mut file));
+        if let Err(error) = written {
+            eprintln!("rpycodestyle: error: {}: {}", output_file, error);
+            process::exit(2);
         }
     }
-}
 
-fn increment_blank_lines(line: &str, &blank_lines: &usize) -> usize {
-    if line.is_empty() {
-        let num_blank_lines = blank_lines + 1;
-        num_blank_lines
-    } else {
-        let num_blank_lines = 0;
-        num_blank_lines
+    if options.count {
+        eprintln!("{}", report.total_errors);
+    }
+    if report.total_errors > 0 {
+        process::exit(1);
     }
 }
-
-
-
-//Logical lines
\ No newline at end of file
@@ -0,0 +1,123 @@
+use diagnostic::Code;
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Noqa {
+    // "# noqa", every code is suppressed.
+    All,
+    // "# noqa: E501,W291", only these codes, or codes starting with them,
+    // are suppressed.
+    Codes(Vec<String>),
+}
+
+impl Noqa {
+    pub fn parse(line: &str) -> Option<Noqa> {
+        // Find a "# noqa" comment in a physical line.
+        //
+        // Matching is case-insensitive, like flake8.  "# noqa:" followed by
+        // something that is not a list of codes suppresses every code.
+        //
+        //     x = 1  # noqa
+        //     x = 1  # NOQA:E501,W291
+        //     x = 1  #noqa: E501 W291
+        let lower = line.to_ascii_lowercase();
+        let mut search = 0;
+        while let Some(index) = lower[search..].find('#') {
+            search += index + 1;
+            let comment = lower[search..].trim_start();
+            if !comment.starts_with("noqa") {
+                continue
+            }
+            let rest = &comment[4..];
+            if rest.starts_with(|char: char| char.is_alphanumeric() || char == '_') {
+                continue
+            }
+            return Some(parse_codes(rest))
+        }
+        None
+    }
+
+    pub fn suppresses(&self, code: Code) -> bool {
+        match *self {
+            Noqa::All => true,
+            Noqa::Codes(ref codes) => codes.iter().any(|prefix| code.as_str().starts_with(prefix.as_str())),
+        }
+    }
+}
+
+fn parse_codes(rest: &str) -> Noqa {
+    let rest = match rest.strip_prefix(':') {
+        Some(rest) => rest,
+        None => return Noqa::All,
+    };
+    let rest = rest.strip_prefix(char::is_whitespace).unwrap_or(rest);
+    let mut codes = Vec::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        let mut code = String::new();
+        while let Some(&char) = chars.peek().filter(|char| char.is_ascii_alphabetic()) {
+            code.push(char.to_ascii_uppercase());
+            chars.next();
+        }
+        let letters = code.len();
+        while let Some(&char) = chars.peek().filter(|char| char.is_ascii_digit()) {
+            code.push(char);
+            chars.next();
+        }
+        if letters == 0 || code.len() == letters {
+            break
+        }
+        codes.push(code);
+        let mut separated = false;
+        while chars.peek().is_some_and(|&char| char == ',' || char.is_whitespace()) {
+            chars.next();
+            separated = true;
+        }
+        if !separated {
+            break
+        }
+    }
+    if codes.is_empty() {
+        Noqa::All
+    } else {
+        Noqa::Codes(codes)
+    }
+}
+
+#[cfg(test)]
+mod test_noqa {
+    use super::*;
+
+    fn codes(codes: &[&str]) -> Option<Noqa> {
+        Some(Noqa::Codes(codes.iter().map(|code| code.to_string()).collect()))
+    }
+
+    #[test]
+    fn noqa_parse_all() {
+        assert_eq!(Noqa::parse("x = 1  # noqa"), Some(Noqa::All));
+        assert_eq!(Noqa::parse("x = 1  #NoQA"), Some(Noqa::All));
+        assert_eq!(Noqa::parse("x = 1  # noqa: not codes"), Some(Noqa::All));
+        assert_eq!(Noqa::parse("# type: int  # noqa"), Some(Noqa::All));
+    }
+
+    #[test]
+    fn noqa_parse_codes() {
+        assert_eq!(Noqa::parse("x = 1  # noqa: E501,W291"), codes(&["E501", "W291"]));
+        assert_eq!(Noqa::parse("x = 1  # NOQA:e501 w2 trailing"), codes(&["E501", "W2"]));
+    }
+
+    #[test]
+    fn noqa_parse_none() {
+        assert_eq!(Noqa::parse("x = 1"), None);
+        assert_eq!(Noqa::parse("x = 1  # noqaa"), None);
+        assert_eq!(Noqa::parse("x = 1  # not noqa"), None);
+    }
+
+    #[test]
+    fn noqa_suppresses() {
+        let noqa = codes(&["E501", "W2"]).unwrap();
+        assert!(noqa.suppresses(Code::new("E501")));
+        assert!(noqa.suppresses(Code::new("W291")));
+        assert!(!noqa.suppresses(Code::new("E231")));
+        assert!(Noqa::All.suppresses(Code::new("E231")));
+    }
+}
@@ -0,0 +1,383 @@
+use std::path::Path;
+
+use diagnostic::Code;
+use files::{normalize_path, path_matches};
+
+pub const DEFAULT_IGNORE: &str = "E121,E123,E126,E226,E24,E704,W503,W504";
+pub const DEFAULT_EXCLUDE: &str = ".svn,CVS,.bzr,.hg,.git,__pycache__,.tox,.nox,.eggs,*.egg";
+pub const DEFAULT_FILENAME: &str = "*.py";
+pub const MAX_LINE_LENGTH: usize = 79;
+// The names accepted by Options::set.
+pub const OPTION_NAMES: &[&str] = &["select", "ignore", "extend-ignore", "max-line-length",
+                                    "max-doc-length", "hang-closing", "count", "first", "quiet",
+                                    "show-source", "show-pep8", "statistics", "benchmark",
+                                    "exclude", "filename", "format", "output-file",
+                                    "per-file-ignores"];
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Options {
+    pub select: Vec<String>,
+    pub ignore: Vec<String>,
+    pub extend_ignore: Vec<String>,
+    pub max_line_length: usize,
+    pub max_doc_length: Option<usize>,
+    pub hang_closing: bool,
+    // The character expected in indentation, E101 is reported for the other.
+    // Files take it from their first indented line, this is the fallback.
+    pub indent_char: char,
+    pub count: bool,
+    pub first: bool,
+    pub quiet: usize,
+    pub show_source: bool,
+    pub show_pep8: bool,
+    pub statistics: bool,
+    pub benchmark: bool,
+    pub exclude: Vec<String>,
+    pub filename: Vec<String>,
+    pub format: String,
+    // Where the document formats are written, the lines of the default
+    // format still go to standard output.
+    pub output_file: Option<String>,
+    // (pattern, codes) pairs, the codes are ignored in the files matching
+    // the pattern.
+    pub per_file_ignores: Vec<(String, Vec<String>)>,
+    // The per-file-ignores codes of the file being checked, see for_path.
+    pub file_ignore: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            select: Vec::new(),
+            ignore: Vec::new(),
+            extend_ignore: Vec::new(),
+            max_line_length: MAX_LINE_LENGTH,
+            max_doc_length: None,
+            hang_closing: false,
+            indent_char: ' ',
+            count: false,
+            first: false,
+            quiet: 0,
+            show_source: false,
+            show_pep8: false,
+            statistics: false,
+            benchmark: false,
+            exclude: parse_list(DEFAULT_EXCLUDE),
+            filename: parse_list(DEFAULT_FILENAME),
+            format: "default".to_string(),
+            output_file: None,
+            per_file_ignores: Vec::new(),
+            file_ignore: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    // Set an option from its command line or configuration file name, with
+    // or without the leading dashes and with "_" or "-" between words.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let name = name.trim_start_matches('-').replace('_', "-");
+        match name.as_str() {
+            "select" => self.select = parse_list(value),
+            "ignore" => self.ignore = parse_list(value),
+            "extend-ignore" => self.extend_ignore.extend(parse_list(value)),
+            "max-line-length" => self.max_line_length = parse_number(&name, value)?,
+            "max-doc-length" => self.max_doc_length = Some(parse_number(&name, value)?),
+            "hang-closing" => self.hang_closing = parse_bool(&name, value)?,
+            "count" => self.count = parse_bool(&name, value)?,
+            "first" => self.first = parse_bool(&name, value)?,
+            "quiet" => self.quiet = parse_number(&name, value)?,
+            "show-source" => self.show_source = parse_bool(&name, value)?,
+            "show-pep8" => self.show_pep8 = parse_bool(&name, value)?,
+            "statistics" => self.statistics = parse_bool(&name, value)?,
+            "benchmark" => self.benchmark = parse_bool(&name, value)?,
+            "exclude" => self.exclude = parse_list(value),
+            "filename" => self.filename = parse_list(value),
+            "format" => self.format = value.trim().to_string(),
+            "output-file" => self.output_file = Some(value.trim().to_string()),
+            "per-file-ignores" => self.per_file_ignores = parse_per_file_ignores(value)?,
+            _ => return Err(format!("no such option: {}", name)),
+        }
+        Ok(())
+    }
+
+    // The options for checking path: the codes of the per-file-ignores
+    // entries whose pattern matches the path are ignored in it.
+    pub fn for_path(&self, path: &Path) -> Options {
+        let mut options = self.clone();
+        for (pattern, codes) in &self.per_file_ignores {
+            let pattern = normalize_path(pattern, Path::new("."));
+            if path_matches(path, &[pattern]) {
+                options.file_ignore.extend(codes.iter().cloned());
+            }
+        }
+        options
+    }
+
+    pub fn ignore_code(&self, code: Code) -> bool {
+        // Check if the error code should be ignored.
+        //
+        // Without select nor ignore the default ignore list applies, with
+        // only select every other code is ignored.
+        let mut ignore: Vec<&str> = if !self.ignore.is_empty() {
+            self.ignore.iter().map(|ignore| ignore.as_str()).collect()
+        } else if !self.select.is_empty() {
+            vec![""]
+        } else {
+            DEFAULT_IGNORE.split(',').collect()
+        };
+        ignore.extend(self.extend_ignore.iter().map(|ignore| ignore.as_str()));
+        let select: Vec<&str> = self.select.iter().map(|select| select.as_str()).collect();
+        let file_ignore: Vec<&str> = self.file_ignore.iter()
+            .map(|ignore| ignore.as_str())
+            .collect();
+        is_ignored(code.as_str(), &select, &ignore, &file_ignore)
+    }
+}
+
+pub fn is_ignored(code: &str, select: &[&str], ignore: &[&str], file_ignore: &[&str]) -> bool {
+//    Decide between select and ignore for a code.
+//
+//    The lists hold code prefixes, "E2" matches every E2xx code.  The
+//    longest prefix matching the code wins, select wins a tie with ignore
+//    and the per-file ignores win a tie with select.  Codes which no ignore
+//    prefix matches are never ignored.  With a select list, codes which no
+//    select prefix matches are always ignored.
+    let longest_match = |prefixes: &[&str]| prefixes.iter()
+        .filter(|prefix| code.starts_with(*prefix))
+        .map(|prefix| prefix.len())
+        .max();
+    let selected = longest_match(select);
+    if !select.is_empty() && selected.is_none() {
+        return true;
+    }
+    let file_ignored = longest_match(file_ignore);
+    if file_ignored.is_some_and(|ignored| selected.is_none_or(|selected| selected <= ignored)) {
+        return true;
+    }
+    match longest_match(ignore) {
+        Some(ignored) => selected.is_none_or(|selected| selected < ignored),
+        None => false,
+    }
+}
+
+pub fn parse_list(value: &str) -> Vec<String> {
+    value.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+fn parse_per_file_ignores(value: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+//    Parse "pattern:codes" entries separated by whitespace.
+//
+//    Codes are separated by commas or whitespace and several patterns can
+//    share the codes, as in flake8:
+//
+//        tests/*.py:E501,E731
+//        __init__.py setup.py: E402
+    let is_code = |word: &str| {
+        let digits = word.trim_start_matches(|char: char| char.is_ascii_uppercase());
+        digits.len() < word.len() && digits.chars().all(|char| char.is_ascii_digit())
+    };
+    let mut entries = Vec::new();
+    let mut patterns: Vec<String> = Vec::new();
+    let mut codes: Vec<String> = Vec::new();
+    let mut seen_colon = false;
+    let words = value.split(|char: char| char == ',' || char.is_whitespace())
+        .flat_map(|word| {
+            let mut parts: Vec<&str> = Vec::new();
+            for (index, part) in word.split(':').enumerate() {
+                if index > 0 {
+                    parts.push(":");
+                }
+                parts.push(part);
+            }
+            parts
+        })
+        .filter(|word| !word.is_empty());
+
+    for word in words {
+        if word == ":" {
+            if seen_colon || patterns.is_empty() {
+                return Err(format!("option --per-file-ignores: invalid value: '{}'", value))
+            }
+            seen_colon = true;
+        } else if seen_colon && is_code(word) {
+            codes.push(word.to_string());
+        } else if seen_colon {
+            entries.extend(patterns.drain(..).map(|pattern| (pattern, codes.clone())));
+            codes.clear();
+            seen_colon = false;
+            patterns.push(word.to_string());
+        } else {
+            patterns.push(word.to_string());
+        }
+    }
+    if !patterns.is_empty() && !seen_colon {
+        return Err(format!("option --per-file-ignores: invalid value: '{}'", value))
+    }
+    entries.extend(patterns.drain(..).map(|pattern| (pattern, codes.clone())));
+    Ok(entries)
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value.trim().parse()
+        .map_err(|_| format!("option --{}: invalid integer value: '{}'", name, value))
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "yes" | "true" | "on" => Ok(true),
+        "0" | "no" | "false" | "off" => Ok(false),
+        _ => Err(format!("option --{}: invalid boolean value: '{}'", name, value)),
+    }
+}
+
+#[cfg(test)]
+mod test_options {
+    use super::*;
+
+    #[test]
+    fn options_set_list() {
+        let mut options = Options::default();
+        options.set("--select", "E1, W2,").unwrap();
+        options.set("extend_ignore", "E501").unwrap();
+        options.set("extend-ignore", "W391").unwrap();
+        assert_eq!(options.select, vec!["E1", "W2"]);
+        assert_eq!(options.extend_ignore, vec!["E501", "W391"]);
+    }
+
+    #[test]
+    fn options_set_values() {
+        let mut options = Options::default();
+        options.set("max-line-length", "100").unwrap();
+        options.set("max_doc_length", "72").unwrap();
+        options.set("show-source", "True").unwrap();
+        assert_eq!(options.max_line_length, 100);
+        assert_eq!(options.max_doc_length, Some(72));
+        assert!(options.show_source);
+    }
+
+    #[test]
+    fn options_set_errors() {
+        let mut options = Options::default();
+        assert_eq!(options.set("max-line-length", "long"),
+                   Err("option --max-line-length: invalid integer value: 'long'".to_string()));
+        assert_eq!(options.set("colour", "1"), Err("no such option: colour".to_string()));
+    }
+
+    #[test]
+    fn options_ignore_code() {
+        let mut options = Options::default();
+        options.set("ignore", "E2,W").unwrap();
+        options.set("select", "E22").unwrap();
+        assert!(options.ignore_code(Code::new("E201")));
+        assert!(!options.ignore_code(Code::new("E221")));
+        assert!(options.ignore_code(Code::new("W291")));
+        assert!(options.ignore_code(Code::new("E501")));
+    }
+
+    #[test]
+    fn options_ignore_code_select_and_ignore() {
+        let mut options = Options::default();
+        options.set("select", "E5").unwrap();
+        options.set("ignore", "E501").unwrap();
+        assert!(options.ignore_code(Code::new("E501")));
+        assert!(!options.ignore_code(Code::new("E502")));
+        assert!(options.ignore_code(Code::new("W291")));
+        assert!(options.ignore_code(Code::new("E231")));
+    }
+
+    #[test]
+    fn is_ignored_longest_match_wins() {
+        assert!(is_ignored("E226", &["E2"], &["E226"], &[]));
+        assert!(!is_ignored("E225", &["E2"], &["E226"], &[]));
+        assert!(!is_ignored("E226", &["E226"], &["E2"], &[]));
+        assert!(!is_ignored("E226", &["E226"], &["E226"], &[]));
+        assert!(is_ignored("W291", &["E"], &[""], &[]));
+        assert!(is_ignored("W291", &["E"], &["E2"], &[]));
+        assert!(!is_ignored("W291", &[], &["E2"], &[]));
+    }
+
+    #[test]
+    fn options_default_ignore() {
+        let options = Options::default();
+        for code in &["E121", "E123", "E126", "E226", "E241", "E242", "E704", "W503", "W504"] {
+            assert!(options.ignore_code(Code::new(code)), "{} should be ignored", code);
+        }
+        assert!(!options.ignore_code(Code::new("E225")));
+        assert!(!options.ignore_code(Code::new("W291")));
+    }
+
+    #[test]
+    fn options_explicit_ignore_replaces_default() {
+        let mut options = Options::default();
+        options.set("ignore", "E501").unwrap();
+        assert!(options.ignore_code(Code::new("E501")));
+        assert!(!options.ignore_code(Code::new("E226")));
+        let mut options = Options::default();
+        options.set("extend-ignore", "E501").unwrap();
+        assert!(options.ignore_code(Code::new("E501")));
+        assert!(options.ignore_code(Code::new("E226")));
+    }
+
+    #[test]
+    fn options_per_file_ignores() {
+        let mut options = Options::default();
+        options.set("per-file-ignores", "tests/*.py:E501,E731\n\
+                                         __init__.py setup.py: F401 E402").unwrap();
+        let entry = |pattern: &str, codes: &[&str]| {
+            (pattern.to_string(), codes.iter().map(|code| code.to_string()).collect())
+        };
+        assert_eq!(options.per_file_ignores, vec![
+            entry("tests/*.py", &["E501", "E731"]),
+            entry("__init__.py", &["F401", "E402"]),
+            entry("setup.py", &["F401", "E402"]),
+        ]);
+        assert_eq!(options.set("per-file-ignores", "E501"),
+                   Err("option --per-file-ignores: invalid value: 'E501'".to_string()));
+        assert_eq!(options.set("per-file-ignores", ":E501"),
+                   Err("option --per-file-ignores: invalid value: ':E501'".to_string()));
+    }
+
+    #[test]
+    fn options_for_path() {
+        let mut options = Options::default();
+        options.set("per-file-ignores", "tests/*.py:E501 __init__.py:E402").unwrap();
+        assert!(options.for_path(Path::new("tests/test_a.py")).ignore_code(Code::new("E501")));
+        assert!(!options.for_path(Path::new("src/a.py")).ignore_code(Code::new("E501")));
+        let options = options.for_path(Path::new("src/pkg/__init__.py"));
+        assert!(options.ignore_code(Code::new("E402")));
+        assert!(!options.ignore_code(Code::new("E501")));
+    }
+
+    #[test]
+    fn options_for_path_wins_over_select() {
+        let mut options = Options::default();
+        options.set("select", "E501,W").unwrap();
+        options.set("per-file-ignores", "foo.py:E501 bar.py:W2").unwrap();
+        assert!(options.for_path(Path::new("foo.py")).ignore_code(Code::new("E501")));
+        assert!(!options.for_path(Path::new("baz.py")).ignore_code(Code::new("E501")));
+        let options = options.for_path(Path::new("bar.py"));
+        assert!(options.ignore_code(Code::new("W291")));
+        assert!(!options.ignore_code(Code::new("W391")));
+    }
+
+    #[test]
+    fn is_ignored_file_ignore_wins_tie() {
+        assert!(is_ignored("E501", &["E501"], &[], &["E501"]));
+        assert!(is_ignored("E501", &["E5"], &[], &["E501"]));
+        assert!(!is_ignored("E501", &["E501"], &[], &["E5"]));
+        assert!(!is_ignored("E501", &["E501"], &["E501"], &[]));
+    }
+
+    #[test]
+    fn options_ignore_code_select_only() {
+        let mut options = Options::default();
+        options.set("select", "E5").unwrap();
+        assert!(!options.ignore_code(Code::new("E501")));
+        assert!(options.ignore_code(Code::new("W291")));
+    }
+}
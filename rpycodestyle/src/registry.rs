@@ -0,0 +1,372 @@
+use diagnostic::{Code, Diagnostic};
+use logical::LogicalLine;
+use options::Options;
+use tokenizer::Token;
+
+// The state handed to a check.  Each kind of check gets the context of its
+// unit of work: a physical line, a logical line or the whole token stream.
+
+pub struct PhysicalLineContext<'a> {
+    // The physical line without its line ending.
+    pub line: &'a str,
+    // The physical line as it appears in the source, line ending included.
+    pub physical_line: &'a str,
+    pub line_number: usize,
+    pub total_lines: usize,
+    // The indent character of the file, see Options::indent_char.
+    pub indent_char: char,
+    pub options: &'a Options,
+}
+
+pub struct LogicalLineContext<'a> {
+    pub logical_line: &'a LogicalLine,
+    // Text of the previous logical line that was not a comment.
+    pub previous_logical: &'a str,
+    // Text of the previous logical line that was not indented.
+    pub previous_unindented_logical: &'a str,
+    pub indent_level: usize,
+    pub previous_indent_level: usize,
+    // The physical lines of the source, line endings included.
+    pub lines: &'a [&'a str],
+    pub indent_char: char,
+    pub options: &'a Options,
+}
+
+pub struct TokenContext<'a> {
+    pub tokens: &'a [Token],
+    // The physical lines of the source, line endings included.
+    pub lines: &'a [&'a str],
+    pub options: &'a Options,
+}
+
+pub trait PhysicalLineCheck {
+    fn name(&self) -> &str;
+    fn codes(&self) -> &[Code];
+    // What the check looks for, as a summary line followed by the rationale
+    // and "Okay:" or "<code>:" example lines.
+    fn doc(&self) -> &str {
+        ""
+    }
+    fn check(&self, context: &PhysicalLineContext) -> Vec<Diagnostic>;
+}
+
+pub trait LogicalLineCheck {
+    fn name(&self) -> &str;
+    fn codes(&self) -> &[Code];
+    // What the check looks for, as a summary line followed by the rationale
+    // and "Okay:" or "<code>:" example lines.
+    fn doc(&self) -> &str {
+        ""
+    }
+    fn check(&self, context: &LogicalLineContext) -> Vec<Diagnostic>;
+}
+
+pub trait TokenCheck {
+    fn name(&self) -> &str;
+    fn codes(&self) -> &[Code];
+    // What the check looks for, as a summary line followed by the rationale
+    // and "Okay:" or "<code>:" example lines.
+    fn doc(&self) -> &str {
+        ""
+    }
+    fn check(&self, context: &TokenContext) -> Vec<Diagnostic>;
+}
+
+// Adapters turning a plain function into a check.
+
+pub struct PhysicalLineFn {
+    name: &'static str,
+    codes: Vec<Code>,
+    doc: &'static str,
+    function: fn(&PhysicalLineContext) -> Vec<Diagnostic>,
+}
+
+impl PhysicalLineFn {
+    pub fn new(name: &'static str, codes: &[&'static str],
+               function: fn(&PhysicalLineContext) -> Vec<Diagnostic>) -> PhysicalLineFn {
+        let codes = codes.iter().map(|&code| Code::new(code)).collect();
+        PhysicalLineFn { name, codes, doc: "", function }
+    }
+
+    pub fn with_doc(mut self, doc: &'static str) -> PhysicalLineFn {
+        self.doc = doc;
+        self
+    }
+}
+
+impl PhysicalLineCheck for PhysicalLineFn {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn codes(&self) -> &[Code] {
+        &self.codes
+    }
+
+    fn doc(&self) -> &str {
+        self.doc
+    }
+
+    fn check(&self, context: &PhysicalLineContext) -> Vec<Diagnostic> {
+        (self.function)(context)
+    }
+}
+
+pub struct LogicalLineFn {
+    name: &'static str,
+    codes: Vec<Code>,
+    doc: &'static str,
+    function: fn(&LogicalLineContext) -> Vec<Diagnostic>,
+}
+
+impl LogicalLineFn {
+    pub fn new(name: &'static str, codes: &[&'static str],
+               function: fn(&LogicalLineContext) -> Vec<Diagnostic>) -> LogicalLineFn {
+        let codes = codes.iter().map(|&code| Code::new(code)).collect();
+        LogicalLineFn { name, codes, doc: "", function }
+    }
+
+    pub fn with_doc(mut self, doc: &'static str) -> LogicalLineFn {
+        self.doc = doc;
+        self
+    }
+}
+
+impl LogicalLineCheck for LogicalLineFn {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn codes(&self) -> &[Code] {
+        &self.codes
+    }
+
+    fn doc(&self) -> &str {
+        self.doc
+    }
+
+    fn check(&self, context: &LogicalLineContext) -> Vec<Diagnostic> {
+        (self.function)(context)
+    }
+}
+
+pub struct TokenFn {
+    name: &'static str,
+    codes: Vec<Code>,
+    doc: &'static str,
+    function: fn(&TokenContext) -> Vec<Diagnostic>,
+}
+
+impl TokenFn {
+    pub fn new(name: &'static str, codes: &[&'static str],
+               function: fn(&TokenContext) -> Vec<Diagnostic>) -> TokenFn {
+        let codes = codes.iter().map(|&code| Code::new(code)).collect();
+        TokenFn { name, codes, doc: "", function }
+    }
+
+    pub fn with_doc(mut self, doc: &'static str) -> TokenFn {
+        self.doc = doc;
+        self
+    }
+}
+
+impl TokenCheck for TokenFn {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn codes(&self) -> &[Code] {
+        &self.codes
+    }
+
+    fn doc(&self) -> &str {
+        self.doc
+    }
+
+    fn check(&self, context: &TokenContext) -> Vec<Diagnostic> {
+        (self.function)(context)
+    }
+}
+
+// A code along with the check which emits it.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Rule<'a> {
+    pub code: Code,
+    pub name: &'a str,
+    pub doc: &'a str,
+}
+
+impl<'a> Rule<'a> {
+    // The doc without its examples, which start at the first line like
+    // "Okay: ..." or "E231: ...".
+    pub fn rationale(&self) -> &'a str {
+        let mut end = 0;
+        for line in self.doc.split_inclusive('\n') {
+            if is_example(line) {
+                break
+            }
+            end += line.len();
+        }
+        self.doc[..end].trim_end()
+    }
+}
+
+fn is_example(line: &str) -> bool {
+    let label = match line.find(':') {
+        Some(index) => &line[..index],
+        None => return false,
+    };
+    let is_code = label.len() == 4 && (label.starts_with('E') || label.starts_with('W')) &&
+        label[1..].chars().all(|char| char.is_ascii_digit());
+    label == "Okay" || is_code
+}
+
+pub struct Registry {
+    pub physical_line_checks: Vec<Box<dyn PhysicalLineCheck>>,
+    pub logical_line_checks: Vec<Box<dyn LogicalLineCheck>>,
+    pub token_checks: Vec<Box<dyn TokenCheck>>,
+}
+
+impl Registry {
+    // An empty registry, see Registry::default for the built-in checks.
+    pub fn new() -> Registry {
+        Registry {
+            physical_line_checks: Vec::new(),
+            logical_line_checks: Vec::new(),
+            token_checks: Vec::new(),
+        }
+    }
+
+    pub fn register_physical_line<C: PhysicalLineCheck + 'static>(&mut self, check: C) {
+        self.physical_line_checks.push(Box::new(check));
+    }
+
+    pub fn register_logical_line<C: LogicalLineCheck + 'static>(&mut self, check: C) {
+        self.logical_line_checks.push(Box::new(check));
+    }
+
+    pub fn register_token<C: TokenCheck + 'static>(&mut self, check: C) {
+        self.token_checks.push(Box::new(check));
+    }
+
+    // Every code that one of the registered checks can emit, sorted.
+    pub fn codes(&self) -> Vec<Code> {
+        let mut codes: Vec<Code> = self.physical_line_checks.iter().flat_map(|check| check.codes())
+            .chain(self.logical_line_checks.iter().flat_map(|check| check.codes()))
+            .chain(self.token_checks.iter().flat_map(|check| check.codes()))
+            .cloned()
+            .collect();
+        codes.sort();
+        codes.dedup();
+        codes
+    }
+
+    // Every code with the name and doc of the first check emitting it,
+    // sorted by code.
+    pub fn rules(&self) -> Vec<Rule<'_>> {
+        let mut rules: Vec<Rule> = Vec::new();
+        let checks = self.physical_line_checks.iter()
+            .map(|check| (check.name(), check.codes(), check.doc()))
+            .chain(self.logical_line_checks.iter()
+                   .map(|check| (check.name(), check.codes(), check.doc())))
+            .chain(self.token_checks.iter()
+                   .map(|check| (check.name(), check.codes(), check.doc())));
+        for (name, codes, doc) in checks {
+            for &code in codes {
+                if !rules.iter().any(|rule| rule.code == code) {
+                    rules.push(Rule { code, name, doc });
+                }
+            }
+        }
+        rules.sort_by_key(|rule| rule.code);
+        rules
+    }
+
+    // The rule of a code, if a registered check emits it.
+    pub fn rule(&self, code: &str) -> Option<Rule<'_>> {
+        self.rules().into_iter().find(|rule| rule.code.as_str() == code)
+    }
+
+    // Drop the checks for which none of the codes they emit is enabled.
+    pub fn retain_enabled<F: Fn(Code) -> bool>(&mut self, enabled: F) {
+        self.physical_line_checks.retain(|check| check.codes().iter().any(|&code| enabled(code)));
+        self.logical_line_checks.retain(|check| check.codes().iter().any(|&code| enabled(code)));
+        self.token_checks.retain(|check| check.codes().iter().any(|&code| enabled(code)));
+    }
+}
+
+impl Default for Registry {
+    // The registry holding every check shipped with rpycodestyle.
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        super::register_builtin_checks(&mut registry);
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test_registry {
+    use super::*;
+
+    fn no_diagnostics(_context: &PhysicalLineContext) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+
+    #[test]
+    fn registry_codes_are_sorted_and_unique() {
+        let mut registry = Registry::new();
+        registry.register_physical_line(PhysicalLineFn::new("b", &["W291", "E501"],
+                                                            no_diagnostics));
+        registry.register_physical_line(PhysicalLineFn::new("a", &["E501"], no_diagnostics));
+        assert_eq!(registry.codes(), vec![Code::new("E501"), Code::new("W291")]);
+    }
+
+    #[test]
+    fn registry_retain_enabled() {
+        let mut registry = Registry::new();
+        registry.register_physical_line(PhysicalLineFn::new("a", &["E501"], no_diagnostics));
+        registry.register_physical_line(PhysicalLineFn::new("b", &["W291", "W293"],
+                                                            no_diagnostics));
+        registry.retain_enabled(|code| code.as_str() != "E501" && code.as_str() != "W291");
+        let names: Vec<&str> = registry.physical_line_checks.iter()
+            .map(|check| check.name())
+            .collect();
+        assert_eq!(names, vec!["b"]);
+    }
+
+    #[test]
+    fn registry_rules() {
+        let mut registry = Registry::new();
+        registry.register_physical_line(PhysicalLineFn::new("b", &["W291", "E501"],
+                                                            no_diagnostics)
+                                        .with_doc("Doc of b."));
+        registry.register_physical_line(PhysicalLineFn::new("a", &["E501"], no_diagnostics));
+        assert_eq!(registry.rules(), vec![
+            Rule { code: Code::new("E501"), name: "b", doc: "Doc of b." },
+            Rule { code: Code::new("W291"), name: "b", doc: "Doc of b." },
+        ]);
+    }
+
+    #[test]
+    fn rule_rationale() {
+        let rule = Rule { code: Code::new("E231"), name: "a",
+                          doc: "Summary.\n\nRationale: why.\n\nOkay: [a, b]\nE231: [a,b]" };
+        assert_eq!(rule.rationale(), "Summary.\n\nRationale: why.");
+        let rule = Rule { code: Code::new("E501"), name: "b", doc: "Summary only.\n" };
+        assert_eq!(rule.rationale(), "Summary only.");
+    }
+
+    #[test]
+    fn default_registry_rule() {
+        let registry = Registry::default();
+        assert_eq!(registry.rule("E231").map(|rule| rule.name), Some("missing_whitespace"));
+        assert_eq!(registry.rule("E999"), None);
+    }
+
+    #[test]
+    fn default_registry_has_builtin_checks() {
+        let codes = Registry::default().codes();
+        assert!(codes.contains(&Code::new("E501")));
+        assert!(codes.contains(&Code::new("E231")));
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::time::Instant;
+
+use diagnostic::{Code, Diagnostic};
+use formats;
+use formats::{FileResult, Template};
+use options::Options;
+use registry::{Registry, Rule};
+
+// Formats which write one document for the whole run instead of a line per
+// diagnostic.
+pub const DOCUMENT_FORMATS: &[&str] = &["json", "sarif", "junit", "checkstyle"];
+
+pub struct Report<'a> {
+    options: &'a Options,
+    pub total_errors: usize,
+    // Number of occurrences and first message of each code.
+    pub counters: BTreeMap<Code, (usize, String)>,
+    pub files: usize,
+    pub physical_lines: usize,
+    // The checked paths and their reported diagnostics, kept for the
+    // document formats.
+    pub results: Vec<FileResult>,
+    // The line format, the default one when writing a document.
+    template: Template,
+    // The built-in checks, for their docs.
+    registry: Registry,
+    start: Instant,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(options: &'a Options) -> Report<'a> {
+        let format = options.format.as_str();
+        let format = if DOCUMENT_FORMATS.contains(&format) { "default" } else { format };
+        let template = Template::from_format(format)
+            .unwrap_or_else(|_| Template::from_format("default").unwrap());
+        Report {
+            options,
+            total_errors: 0,
+            counters: BTreeMap::new(),
+            files: 0,
+            physical_lines: 0,
+            results: Vec::new(),
+            template,
+            registry: Registry::default(),
+            start: Instant::now(),
+        }
+    }
+
+    // Check that the format option names a known format or is a valid
+    // template.
+    pub fn validate(options: &Options) -> Result<(), String> {
+        match options.format.as_str() {
+            "github" => Ok(()),
+            format if DOCUMENT_FORMATS.contains(&format) => Ok(()),
+            format if format.contains('%') || formats::TEMPLATES.iter()
+                .any(|&(name, _)| name == format.to_lowercase()) =>
+                Template::from_format(format).map(|_| ()),
+            format => Err(format!("unknown format: {}", format)),
+        }
+    }
+
+    pub fn file(&mut self, out: &mut dyn Write, path: &str, source: &str,
+                diagnostics: &[Diagnostic]) -> io::Result<()> {
+        self.files += 1;
+        self.physical_lines += source.lines().count();
+        let lines: Vec<&str> = source.lines().collect();
+        // Show only the first occurrence of each code with --first.
+        let repeat = !(self.options.first || self.options.show_pep8);
+        let document = DOCUMENT_FORMATS.contains(&self.options.format.as_str());
+        let mut reported = Vec::new();
+
+        for (index, diagnostic) in diagnostics.iter().enumerate() {
+            self.total_errors += 1;
+            let counter = self.counters.entry(diagnostic.code)
+                .or_insert((0, diagnostic.message.clone()));
+            counter.0 += 1;
+            let first = counter.0 == 1;
+
+            if document {
+                if first || repeat {
+                    reported.push(diagnostic.clone());
+                }
+                // The document goes to the output file, leaving standard
+                // output to the default format.
+                if self.options.output_file.is_none() {
+                    continue
+                }
+            }
+            match self.options.quiet {
+                0 => (),
+                1 => {
+                    if index == 0 {
+                        writeln!(out, "{}", path)?;
+                    }
+                    continue
+                }
+                _ => continue,
+            }
+            if !first && !repeat {
+                continue
+            }
+            self.print(out, path, diagnostic)?;
+            if self.options.show_source {
+                let line = lines.get(diagnostic.line - 1).cloned().unwrap_or("");
+                let indent: String = line.chars()
+                    .take(diagnostic.column)
+                    .map(|char| if char.is_whitespace() { char } else { ' ' })
+                    .collect();
+                writeln!(out, "{}", line.trim_end())?;
+                writeln!(out, "{}^", indent)?;
+            }
+            if self.options.show_pep8 {
+                if let Some(rule) = self.registry.rule(diagnostic.code.as_str()) {
+                    for line in rule.rationale().lines() {
+                        writeln!(out, "{}", format!("    {}", line).trim_end())?;
+                    }
+                }
+            }
+        }
+        if document {
+            self.results.push((path.to_string(), reported));
+        }
+        Ok(())
+    }
+
+    fn print(&self, out: &mut dyn Write, path: &str, diagnostic: &Diagnostic) -> io::Result<()> {
+        if self.options.format == "github" {
+            // A workflow command, shown by GitHub Actions as an annotation.
+            return writeln!(out, "::{} file={},line={},col={},title={}::{}",
+                            diagnostic.severity.as_str(), formats::github_escape(path, true),
+                            diagnostic.line, diagnostic.column + 1, diagnostic.code,
+                            formats::github_escape(&diagnostic.message, false))
+        }
+        writeln!(out, "{}", self.template.render(path, diagnostic))
+    }
+
+    // Write the document of a document format, with the results of every
+    // checked file.
+    pub fn write_document(&self, out: &mut dyn Write) -> io::Result<()> {
+        match self.options.format.as_str() {
+            "json" => formats::write_json(out, &self.results),
+            "sarif" => formats::write_sarif(out, &self.results, &self.registry.rules()),
+            "junit" => formats::write_junit(out, &self.results),
+            "checkstyle" => formats::write_checkstyle(out, &self.results),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn finish(&self, out: &mut dyn Write) -> io::Result<()> {
+        if DOCUMENT_FORMATS.contains(&self.options.format.as_str()) &&
+            self.options.output_file.is_none() {
+            // Statistics and benchmark figures would break the document.
+            return self.write_document(out)
+        }
+        if self.options.statistics {
+            for (code, &(count, ref message)) in &self.counters {
+                writeln!(out, "{:<7} {} {}", count, code, message)?;
+            }
+        }
+        if self.options.benchmark {
+            let elapsed = self.start.elapsed().as_secs_f64();
+            writeln!(out, "{:<7.2} seconds elapsed", elapsed)?;
+            if elapsed > 0.0 {
+                for &(name, count) in &[("files", self.files),
+                                        ("physical lines", self.physical_lines)] {
+                    writeln!(out, "{:<7} {} per second ({} total)",
+                             (count as f64 / elapsed) as usize, name, count)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The full doc of a rule, examples included, for "rpycodestyle explain".
+pub fn write_explanation(out: &mut dyn Write, rule: &Rule) -> io::Result<()> {
+    writeln!(out, "{} ({})", rule.code, rule.name)?;
+    writeln!(out)?;
+    writeln!(out, "{}", rule.doc.trim_end())
+}
+
+#[cfg(test)]
+mod test_report {
+    use super::*;
+    use check_source;
+
+    fn output(options: &Options, source: &str) -> String {
+        let mut out = Vec::new();
+        let mut report = Report::new(options);
+        let diagnostics = check_source(source, options);
+        report.file(&mut out, "test.py", source, &diagnostics).unwrap();
+        report.finish(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn report_default_format() {
+        let output = output(&Options::default(), "x = [1,2]\n");
+        assert_eq!(output, "test.py:1:7: E231 missing whitespace after ,\n");
+    }
+
+    #[test]
+    fn report_pylint_format() {
+        let mut options = Options::default();
+        options.set("format", "pylint").unwrap();
+        let output = output(&options, "x = [1,2]\n");
+        assert_eq!(output, "test.py:1: [E231] missing whitespace after ,\n");
+    }
+
+    #[test]
+    fn report_template_format() {
+        let mut options = Options::default();
+        options.set("format", "%(path)s|%(row)d|%(col)d|%(code)s|%(text)s").unwrap();
+        let output = output(&options, "x = [1,2]\n");
+        assert_eq!(output, "test.py|1|7|E231|missing whitespace after ,\n");
+    }
+
+    #[test]
+    fn report_validate_format() {
+        let mut options = Options::default();
+        for format in &["default", "PYLINT", "github", "sarif", "%(path)s: %(text)s"] {
+            options.set("format", format).unwrap();
+            assert_eq!(Report::validate(&options), Ok(()));
+        }
+        options.set("format", "pylnt").unwrap();
+        assert_eq!(Report::validate(&options), Err("unknown format: pylnt".to_string()));
+        options.set("format", "%(line)d").unwrap();
+        assert_eq!(Report::validate(&options),
+                   Err("invalid format '%(line)d': unknown field 'line'".to_string()));
+    }
+
+    #[test]
+    fn report_github_format() {
+        let mut options = Options::default();
+        options.set("format", "github").unwrap();
+        let output = output(&options, "x = [1,2] \n");
+        assert_eq!(output, "::error file=test.py,line=1,col=7,title=E231::\
+                            missing whitespace after ,\n\
+                            ::warning file=test.py,line=1,col=10,title=W291::\
+                            trailing whitespace\n");
+    }
+
+    #[test]
+    fn report_first_and_statistics() {
+        let mut options = Options::default();
+        options.set("first", "true").unwrap();
+        options.set("statistics", "true").unwrap();
+        let output = output(&options, "x = [1,2,3]\n");
+        assert_eq!(output, "test.py:1:7: E231 missing whitespace after ,\n\
+                            2       E231 missing whitespace after ,\n");
+    }
+
+    #[test]
+    fn report_show_source() {
+        let mut options = Options::default();
+        options.set("show-source", "true").unwrap();
+        let output = output(&options, "if x:\n    y = [1,2]\n");
+        assert_eq!(output, "test.py:2:11: E231 missing whitespace after ,\n    \
+                            y = [1,2]\n          ^\n");
+    }
+
+    #[test]
+    fn report_show_source_non_ascii() {
+        let mut options = Options::default();
+        options.set("show-source", "true").unwrap();
+        let output = output(&options, "x = ['é',\t'ü',2]\n");
+        assert_eq!(output, "test.py:1:14: E231 missing whitespace after ,\n\
+                            x = ['é',\t'ü',2]\n         \t   ^\n");
+    }
+
+    #[test]
+    fn report_show_pep8() {
+        let mut options = Options::default();
+        options.set("show-pep8", "true").unwrap();
+        let output = output(&options, "x = [1,2,3]\n");
+        assert_eq!(output, "test.py:1:7: E231 missing whitespace after ,\n    \
+                            Each comma, semicolon or colon should be followed by whitespace.\n");
+    }
+
+    #[test]
+    fn explanation() {
+        let registry = Registry::default();
+        let mut out = Vec::new();
+        write_explanation(&mut out, &registry.rule("W191").unwrap()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "W191 (tabs_obsolete)\n\n\
+            On new projects, spaces-only are strongly recommended over tabs.\n\n\
+            Okay: if True:\\n    return\nW191: if True:\\n\\treturn\n");
+    }
+
+    #[test]
+    fn report_json_format() {
+        let mut options = Options::default();
+        options.set("format", "json").unwrap();
+        options.set("statistics", "true").unwrap();
+        let output = output(&options, "x = [1,2]\n");
+        assert_eq!(output, "[\n  {\"path\": \"test.py\", \"line\": 1, \"column\": 7, \
+                            \"end_column\": 8, \"code\": \"E231\", \
+                            \"message\": \"missing whitespace after ,\", \
+                            \"severity\": \"error\"}\n]\n");
+    }
+
+    #[test]
+    fn report_sarif_format() {
+        let mut options = Options::default();
+        options.set("format", "sarif").unwrap();
+        let output = output(&options, "x = [1,2]\n");
+        assert!(output.starts_with("{\n  \"$schema\": "));
+        assert!(output.contains("\"id\": \"W191\",\n              \"name\": \"tabs_obsolete\""));
+        assert!(output.contains("\"ruleId\": \"E231\""));
+        assert!(output.contains("\"message\": {\"text\": \"missing whitespace after ,\"}"));
+        assert!(output.contains("\"uri\": \"test.py\""));
+    }
+
+    #[test]
+    fn report_junit_format() {
+        let mut options = Options::default();
+        options.set("format", "junit").unwrap();
+        let output = output(&options, "x = [1,2]\n");
+        assert!(output.contains("<testsuite name=\"rpycodestyle\" tests=\"1\" failures=\"1\""));
+        assert!(output.contains("test.py:1:7: E231 missing whitespace after ,</failure>"));
+    }
+
+    #[test]
+    fn report_checkstyle_format() {
+        let mut options = Options::default();
+        options.set("format", "checkstyle").unwrap();
+        let output = output(&options, "x = [1,2]\n");
+        assert!(output.contains("<file name=\"test.py\">\n    <error line=\"1\" column=\"7\" \
+                                 severity=\"error\" message=\"missing whitespace after ,\" \
+                                 source=\"rpycodestyle.E231\"/>"));
+    }
+
+    #[test]
+    fn report_output_file_keeps_default_format() {
+        let mut options = Options::default();
+        options.set("format", "junit").unwrap();
+        options.set("output-file", "report.xml").unwrap();
+        options.set("statistics", "true").unwrap();
+        let mut out = Vec::new();
+        let mut report = Report::new(&options);
+        let diagnostics = check_source("x = [1,2]\n", &options);
+        report.file(&mut out, "test.py", "x = [1,2]\n", &diagnostics).unwrap();
+        report.finish(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "test.py:1:7: E231 missing whitespace after ,\n\
+                    1       E231 missing whitespace after ,\n");
+        let mut document = Vec::new();
+        report.write_document(&mut document).unwrap();
+        assert!(String::from_utf8(document).unwrap().contains("<testcase "));
+    }
+
+    #[test]
+    fn report_quiet() {
+        let mut options = Options::default();
+        options.set("quiet", "1").unwrap();
+        assert_eq!(output(&options, "x = [1,2,3]\n"), "test.py\n");
+        options.set("quiet", "2").unwrap();
+        assert_eq!(output(&options, "x = [1,2,3]\n"), "");
+    }
+}
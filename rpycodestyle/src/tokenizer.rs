@@ -0,0 +1,598 @@
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum TokenType {
+    Name,
+    Op,
+    Number,
+    String,
+    Comment,
+    NL,
+    Newline,
+    Indent,
+    Dedent,
+    EndMarker,
+    ErrorToken,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub text: String,
+    // (row, column) pairs, rows start at 1 and columns are character offsets
+    // starting at 0, the same convention Python's tokenize module uses.
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub line: String,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct TokenError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+const OPERATORS: [&str; 47] = [
+    "**=", "//=", ">>=", "<<=", "...",
+    "**", "//", ">>", "<<", "<=", ">=", "==", "!=", "->", "+=", "-=", "*=",
+    "/=", "%=", "&=", "|=", "^=", "@=", ":=",
+    "@", "+", "-", "*", "/", "%", "&", "|", "^", "~", "<", ">", "(", ")", "[",
+    "]", "{", "}", ",", ":", ";", ".", "=",
+];
+
+struct PendingString {
+    quote: Vec<char>,
+    text: String,
+    start: (usize, usize),
+    line: String,
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, TokenError> {
+//    Split Python source into tokens.
+//
+//    This follows the pure Python implementation of the tokenize module:
+//    INDENT and DEDENT are only produced at the start of a statement, blank
+//    and comment-only lines produce NL instead of NEWLINE, and physical lines
+//    inside brackets or after a backslash are part of the same statement.
+    let mut tokens = Vec::new();
+    let mut indents = vec![0];
+    let mut parenlev = 0;
+    let mut continued = false;
+    let mut pending: Option<PendingString> = None;
+    let lines = split_lines(source);
+    let mut last_line = String::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let line_number = index + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let max = chars.len();
+        let mut pos = 0;
+        last_line = line.to_string();
+
+        if let Some(mut string) = pending.take() {
+            match find_string_end(&chars, 0, &string.quote) {
+                Some(end) => {
+                    string.text.extend(&chars[..end]);
+                    string.line.push_str(line);
+                    tokens.push(Token {
+                        token_type: TokenType::String,
+                        text: string.text,
+                        start: string.start,
+                        end: (line_number, end),
+                        line: string.line,
+                    });
+                    pos = end;
+                }
+                None => {
+                    if string.quote.len() == 1 && !ends_with_continuation(&chars) {
+                        string.text.push_str(line);
+                        string.line.push_str(line);
+                        tokens.push(Token {
+                            token_type: TokenType::ErrorToken,
+                            text: string.text,
+                            start: string.start,
+                            end: (line_number, max),
+                            line: string.line,
+                        });
+                    } else {
+                        string.text.push_str(line);
+                        string.line.push_str(line);
+                        pending = Some(string);
+                    }
+                    continue
+                }
+            }
+        } else if parenlev == 0 && !continued {
+            let mut column = 0;
+            while pos < max {
+                match chars[pos] {
+                    ' ' => column += 1,
+                    '\t' => column = (column / 8 + 1) * 8,
+                    '\x0c' => column = 0,
+                    _ => break,
+                }
+                pos += 1;
+            }
+            if pos == max {
+                continue
+            }
+
+            if chars[pos] == '#' || is_newline(chars[pos]) {
+                if chars[pos] == '#' {
+                    let comment_end = line_content_end(&chars);
+                    tokens.push(token(TokenType::Comment, &chars, line_number,
+                                      pos, comment_end, line));
+                    pos = comment_end;
+                }
+                tokens.push(token(TokenType::NL, &chars, line_number, pos, max,
+                                  line));
+                continue
+            }
+
+            if column > *indents.last().unwrap() {
+                indents.push(column);
+                tokens.push(token(TokenType::Indent, &chars, line_number, 0, pos,
+                                  line));
+            }
+            while column < *indents.last().unwrap() {
+                if !indents.contains(&column) {
+                    return Err(TokenError {
+                        message: "unindent does not match any outer indentation level"
+                            .to_string(),
+                        line: line_number,
+                        column: pos,
+                    })
+                }
+                indents.pop();
+                tokens.push(token(TokenType::Dedent, &chars, line_number, pos, pos,
+                                  line));
+            }
+        } else {
+            continued = false;
+        }
+
+        while pos < max {
+            while pos < max && (chars[pos] == ' ' || chars[pos] == '\t' ||
+                                chars[pos] == '\x0c') {
+                pos += 1;
+            }
+            if pos == max {
+                break
+            }
+            let start = pos;
+            let initial = chars[pos];
+
+            if initial.is_ascii_digit() ||
+                (initial == '.' && pos + 1 < max && chars[pos + 1].is_ascii_digit()) {
+                pos = scan_number(&chars, pos);
+                tokens.push(token(TokenType::Number, &chars, line_number, start,
+                                  pos, line));
+            } else if is_newline(initial) {
+                let token_type = if parenlev > 0 {
+                    TokenType::NL
+                } else {
+                    TokenType::Newline
+                };
+                tokens.push(token(token_type, &chars, line_number, start, max,
+                                  line));
+                pos = max;
+            } else if initial == '#' {
+                pos = line_content_end(&chars);
+                tokens.push(token(TokenType::Comment, &chars, line_number, start,
+                                  pos, line));
+            } else if let Some(quote_start) = string_prefix_end(&chars, pos) {
+                let quote = string_quote(&chars, quote_start);
+                match find_string_end(&chars, quote_start + quote.len(), &quote) {
+                    Some(end) => {
+                        pos = end;
+                        tokens.push(token(TokenType::String, &chars, line_number,
+                                          start, pos, line));
+                    }
+                    None => {
+                        if quote.len() == 3 || ends_with_continuation(&chars) {
+                            pending = Some(PendingString {
+                                quote,
+                                text: chars[start..].iter().collect(),
+                                start: (line_number, start),
+                                line: line.to_string(),
+                            });
+                            pos = max;
+                        } else {
+                            pos = quote_start + 1;
+                            tokens.push(token(TokenType::ErrorToken, &chars,
+                                              line_number, start, pos, line));
+                        }
+                    }
+                }
+            } else if is_identifier_start(initial) {
+                while pos < max && is_identifier_char(chars[pos]) {
+                    pos += 1;
+                }
+                tokens.push(token(TokenType::Name, &chars, line_number, start,
+                                  pos, line));
+            } else if initial == '\\' && pos + 1 < max && is_newline(chars[pos + 1]) {
+                continued = true;
+                pos = max;
+            } else {
+                match match_operator(&chars, pos) {
+                    Some(operator) => {
+                        match operator {
+                            "(" | "[" | "{" => parenlev += 1,
+                            ")" | "]" | "}" if parenlev > 0 => parenlev -= 1,
+                            _ => (),
+                        }
+                        pos += operator.len();
+                        tokens.push(token(TokenType::Op, &chars, line_number,
+                                          start, pos, line));
+                    }
+                    None => {
+                        pos += 1;
+                        tokens.push(token(TokenType::ErrorToken, &chars,
+                                          line_number, start, pos, line));
+                    }
+                }
+            }
+        }
+    }
+
+    let line_number = lines.len() + 1;
+    if let Some(string) = pending {
+        return Err(TokenError {
+            message: "EOF in multi-line string".to_string(),
+            line: string.start.0,
+            column: string.start.1,
+        })
+    }
+    if parenlev > 0 || continued {
+        return Err(TokenError {
+            message: "EOF in multi-line statement".to_string(),
+            line: line_number,
+            column: 0,
+        })
+    }
+
+    let last_chars: Vec<char> = last_line.chars().collect();
+    if !last_chars.is_empty() && !is_newline(*last_chars.last().unwrap()) &&
+        !last_line.trim_start().starts_with('#') {
+        let row = line_number - 1;
+        let column = last_chars.len();
+        tokens.push(Token {
+            token_type: TokenType::Newline,
+            text: String::new(),
+            start: (row, column),
+            end: (row, column + 1),
+            line: String::new(),
+        });
+    }
+    for _ in 1..indents.len() {
+        tokens.push(Token {
+            token_type: TokenType::Dedent,
+            text: String::new(),
+            start: (line_number, 0),
+            end: (line_number, 0),
+            line: String::new(),
+        });
+    }
+    tokens.push(Token {
+        token_type: TokenType::EndMarker,
+        text: String::new(),
+        start: (line_number, 0),
+        end: (line_number, 0),
+        line: String::new(),
+    });
+    Ok(tokens)
+}
+
+fn split_lines(source: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (index, char) in source.char_indices() {
+        if char == '\n' {
+            lines.push(&source[start..index + 1]);
+            start = index + 1;
+        }
+    }
+    if start < source.len() {
+        lines.push(&source[start..]);
+    }
+    lines
+}
+
+fn token(token_type: TokenType, chars: &[char], line_number: usize, start: usize,
+         end: usize, line: &str) -> Token {
+    Token {
+        token_type,
+        text: chars[start..end].iter().collect(),
+        start: (line_number, start),
+        end: (line_number, end),
+        line: line.to_string(),
+    }
+}
+
+fn is_newline(char: char) -> bool {
+    char == '\n' || char == '\r'
+}
+
+fn is_identifier_start(char: char) -> bool {
+    char == '_' || char.is_alphabetic()
+}
+
+fn is_identifier_char(char: char) -> bool {
+    char == '_' || char.is_alphanumeric()
+}
+
+fn line_content_end(chars: &[char]) -> usize {
+    let mut end = chars.len();
+    while end > 0 && is_newline(chars[end - 1]) {
+        end -= 1;
+    }
+    end
+}
+
+fn ends_with_continuation(chars: &[char]) -> bool {
+    let end = line_content_end(chars);
+    end < chars.len() && end > 0 && chars[end - 1] == '\\'
+}
+
+fn match_operator(chars: &[char], pos: usize) -> Option<&'static str> {
+    for operator in OPERATORS.iter() {
+        let length = operator.chars().count();
+        if pos + length <= chars.len() &&
+            operator.chars().zip(&chars[pos..pos + length]).all(|(a, &b)| a == b) {
+            return Some(operator)
+        }
+    }
+    None
+}
+
+fn string_prefix_end(chars: &[char], pos: usize) -> Option<usize> {
+    let mut end = pos;
+    while end < chars.len() && end - pos < 3 && "rRbBuUfF".contains(chars[end]) {
+        end += 1;
+    }
+    if end == chars.len() || (chars[end] != '"' && chars[end] != '\'') {
+        return None
+    }
+    let prefix: String = chars[pos..end].iter().collect::<String>().to_lowercase();
+    match prefix.as_str() {
+        "" | "r" | "u" | "b" | "f" | "br" | "rb" | "fr" | "rf" => Some(end),
+        _ => None,
+    }
+}
+
+fn string_quote(chars: &[char], pos: usize) -> Vec<char> {
+    let quote = chars[pos];
+    if pos + 2 < chars.len() && chars[pos + 1] == quote && chars[pos + 2] == quote {
+        vec![quote; 3]
+    } else {
+        vec![quote]
+    }
+}
+
+fn find_string_end(chars: &[char], from: usize, quote: &[char]) -> Option<usize> {
+    let mut pos = from;
+    while pos < chars.len() {
+        if chars[pos] == '\\' {
+            pos += 2;
+            continue
+        }
+        if quote.len() == 1 && is_newline(chars[pos]) {
+            return None
+        }
+        if chars[pos..].starts_with(quote) {
+            return Some(pos + quote.len())
+        }
+        pos += 1;
+    }
+    None
+}
+
+fn scan_number(chars: &[char], start: usize) -> usize {
+    let digits = |pos: usize, radix: u32| {
+        let mut pos = pos;
+        while pos < chars.len() && (chars[pos].is_digit(radix) || chars[pos] == '_') {
+            pos += 1;
+        }
+        pos
+    };
+    let mut pos = start;
+    if chars[pos] == '0' && pos + 1 < chars.len() {
+        let radix = match chars[pos + 1] {
+            'x' | 'X' => 16,
+            'o' | 'O' => 8,
+            'b' | 'B' => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            return digits(pos + 2, radix)
+        }
+    }
+    pos = digits(pos, 10);
+    if pos < chars.len() && chars[pos] == '.' {
+        pos = digits(pos + 1, 10);
+    }
+    if pos < chars.len() && (chars[pos] == 'e' || chars[pos] == 'E') {
+        let mut exponent = pos + 1;
+        if exponent < chars.len() && (chars[exponent] == '+' || chars[exponent] == '-') {
+            exponent += 1;
+        }
+        if exponent < chars.len() && chars[exponent].is_ascii_digit() {
+            pos = digits(exponent, 10);
+        }
+    }
+    if pos < chars.len() && (chars[pos] == 'j' || chars[pos] == 'J') {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod test_tokenizer {
+    use super::*;
+
+    fn types_and_text(source: &str) -> Vec<(TokenType, String)> {
+        tokenize(source).unwrap().into_iter()
+            .map(|token| (token.token_type, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_simple_statement() {
+        let tokens = tokenize("a = 1\n").unwrap();
+        let expected = vec![
+            (TokenType::Name, "a", (1, 0), (1, 1)),
+            (TokenType::Op, "=", (1, 2), (1, 3)),
+            (TokenType::Number, "1", (1, 4), (1, 5)),
+            (TokenType::Newline, "\n", (1, 5), (1, 6)),
+            (TokenType::EndMarker, "", (2, 0), (2, 0)),
+        ];
+        assert_eq!(tokens.len(), expected.len());
+        for (token, (token_type, text, start, end)) in tokens.iter().zip(expected) {
+            assert_eq!(token.token_type, token_type);
+            assert_eq!(token.text, text);
+            assert_eq!(token.start, start);
+            assert_eq!(token.end, end);
+        }
+    }
+
+    #[test]
+    fn tokenize_string_hides_operators() {
+        let tokens = types_and_text("a = \"x  + y\"  # b, c\n");
+        assert_eq!(tokens, vec![
+            (TokenType::Name, "a".to_string()),
+            (TokenType::Op, "=".to_string()),
+            (TokenType::String, "\"x  + y\"".to_string()),
+            (TokenType::Comment, "# b, c".to_string()),
+            (TokenType::Newline, "\n".to_string()),
+            (TokenType::EndMarker, "".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tokenize_indent_and_dedent() {
+        let tokens = types_and_text("if a:\n    b\nc\n");
+        let token_types: Vec<TokenType> = tokens.into_iter().map(|(t, _)| t).collect();
+        assert_eq!(token_types, vec![
+            TokenType::Name, TokenType::Name, TokenType::Op, TokenType::Newline,
+            TokenType::Indent, TokenType::Name, TokenType::Newline,
+            TokenType::Dedent, TokenType::Name, TokenType::Newline,
+            TokenType::EndMarker,
+        ]);
+    }
+
+    #[test]
+    fn tokenize_blank_and_comment_lines_are_nl() {
+        let tokens = types_and_text("\n# hi\n");
+        assert_eq!(tokens, vec![
+            (TokenType::NL, "\n".to_string()),
+            (TokenType::Comment, "# hi".to_string()),
+            (TokenType::NL, "\n".to_string()),
+            (TokenType::EndMarker, "".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tokenize_newline_inside_brackets_is_nl() {
+        let tokens = types_and_text("f(a,\n  b)\n");
+        let token_types: Vec<TokenType> = tokens.into_iter().map(|(t, _)| t).collect();
+        assert_eq!(token_types, vec![
+            TokenType::Name, TokenType::Op, TokenType::Name, TokenType::Op,
+            TokenType::NL, TokenType::Name, TokenType::Op, TokenType::Newline,
+            TokenType::EndMarker,
+        ]);
+    }
+
+    #[test]
+    fn tokenize_backslash_continuation() {
+        let tokens = tokenize("a = 1 + \\\n    2\n").unwrap();
+        let two = &tokens[4];
+        assert_eq!(two.text, "2");
+        assert_eq!(two.start, (2, 4));
+        assert_eq!(tokens[5].token_type, TokenType::Newline);
+    }
+
+    #[test]
+    fn tokenize_triple_quoted_string() {
+        let tokens = tokenize("x = '''a\nb'''\n").unwrap();
+        let string = &tokens[2];
+        assert_eq!(string.token_type, TokenType::String);
+        assert_eq!(string.text, "'''a\nb'''");
+        assert_eq!(string.start, (1, 4));
+        assert_eq!(string.end, (2, 4));
+    }
+
+    #[test]
+    fn tokenize_string_prefixes() {
+        let tokens = types_and_text("rb'a' f\"b\" u'c'\n");
+        assert_eq!(tokens[0], (TokenType::String, "rb'a'".to_string()));
+        assert_eq!(tokens[1], (TokenType::String, "f\"b\"".to_string()));
+        assert_eq!(tokens[2], (TokenType::String, "u'c'".to_string()));
+    }
+
+    #[test]
+    fn tokenize_numbers() {
+        let tokens = types_and_text("0x1F 1_000 3.14e-10 .5 2j\n");
+        let numbers: Vec<String> = tokens.into_iter()
+            .filter(|&(t, _)| t == TokenType::Number)
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(numbers, vec!["0x1F", "1_000", "3.14e-10", ".5", "2j"]);
+    }
+
+    #[test]
+    fn tokenize_multi_character_operators() {
+        let tokens = types_and_text("a **= b // c -> d := e != f <> g\n");
+        let operators: Vec<String> = tokens.into_iter()
+            .filter(|&(t, _)| t == TokenType::Op)
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(operators, vec!["**=", "//", "->", ":=", "!=", "<", ">"]);
+    }
+
+    #[test]
+    fn tokenize_at_operator() {
+        let tokens = types_and_text("@dec\ndef f(): a = b@c\n");
+        assert!(tokens.iter().all(|&(t, _)| t != TokenType::ErrorToken));
+        assert_eq!(tokens[0], (TokenType::Op, "@".to_string()));
+        assert_eq!(tokens[1], (TokenType::Name, "dec".to_string()));
+        assert_eq!(&tokens[10..13], &[
+            (TokenType::Name, "b".to_string()),
+            (TokenType::Op, "@".to_string()),
+            (TokenType::Name, "c".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tokenize_columns_count_characters() {
+        let tokens = tokenize("é = 'ü'\n").unwrap();
+        assert_eq!(tokens[1].start, (1, 2));
+        assert_eq!(tokens[2].end, (1, 7));
+    }
+
+    #[test]
+    fn tokenize_missing_final_newline() {
+        let tokens = tokenize("a").unwrap();
+        assert_eq!(tokens[1].token_type, TokenType::Newline);
+        assert_eq!(tokens[1].text, "");
+        assert_eq!(tokens[1].start, (1, 1));
+    }
+
+    #[test]
+    fn tokenize_unterminated_triple_quoted_string() {
+        let error = tokenize("x = '''a\n").unwrap_err();
+        assert_eq!(error.message, "EOF in multi-line string");
+        assert_eq!((error.line, error.column), (1, 4));
+    }
+
+    #[test]
+    fn tokenize_unclosed_bracket() {
+        let error = tokenize("x = (1,\n").unwrap_err();
+        assert_eq!(error.message, "EOF in multi-line statement");
+    }
+
+    #[test]
+    fn tokenize_bad_dedent() {
+        let error = tokenize("if a:\n    b\n  c\n").unwrap_err();
+        assert_eq!(error.message, "unindent does not match any outer indentation level");
+        assert_eq!(error.line, 3);
+    }
+}